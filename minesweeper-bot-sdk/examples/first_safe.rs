@@ -0,0 +1,21 @@
+//! Reveal the first cell known to be safe, or the first hidden cell when none is known
+
+use minesweeper_bot_sdk::{run, Action, Board, Bot};
+
+struct FirstSafeBot;
+
+impl Bot for FirstSafeBot {
+    fn next_action(&mut self, board: &Board) -> Action {
+        let (x, y) = board
+            .known_safe()
+            .first()
+            .or(board.hidden_positions().first())
+            .copied()
+            .unwrap_or((0, 0));
+        Action::Reveal { x, y }
+    }
+}
+
+fn main() {
+    run(FirstSafeBot)
+}
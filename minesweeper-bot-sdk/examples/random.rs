@@ -0,0 +1,21 @@
+//! Reveal a random hidden cell each move
+
+use minesweeper_bot_sdk::{run, Action, Board, Bot};
+use rand::seq::SliceRandom;
+
+struct RandomBot;
+
+impl Bot for RandomBot {
+    fn next_action(&mut self, board: &Board) -> Action {
+        let (x, y) = board
+            .hidden_positions()
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .unwrap_or((0, 0));
+        Action::Reveal { x, y }
+    }
+}
+
+fn main() {
+    run(RandomBot)
+}
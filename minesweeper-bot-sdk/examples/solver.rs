@@ -0,0 +1,125 @@
+//! Flag the known bombs and reveal every known safe cell at once, comparing the constraints of
+//! neighbour cells when the simple rules are not enough, and guess the hidden cell least likely
+//! to be a bomb as a last resort
+
+use minesweeper_bot_sdk::{run, Action, Board, Bot, MinesweeperCellType};
+use std::collections::HashSet;
+
+struct SolverBot;
+
+type Positions = Vec<(usize, usize)>;
+
+/// Hidden cells around a revealed cell and the number of bombs among them
+struct Constraint {
+    cells: HashSet<(usize, usize)>,
+    bombs: usize,
+}
+
+fn constraints(board: &Board) -> Vec<Constraint> {
+    board
+        .positions()
+        .filter_map(|(xpos, ypos)| {
+            let Some(MinesweeperCellType::Found(count)) = board.cell(xpos, ypos) else {
+                return None;
+            };
+            let neighbours = board.unrevealed_neighbours(xpos, ypos);
+            let flags = neighbours
+                .iter()
+                .filter(|&&(x, y)| board.cell(x, y) == Some(MinesweeperCellType::Flagged))
+                .count();
+            let cells = neighbours
+                .into_iter()
+                .filter(|&(x, y)| board.cell(x, y) == Some(MinesweeperCellType::Hidden))
+                .collect::<HashSet<_>>();
+            (!cells.is_empty()).then_some(Constraint {
+                cells,
+                bombs: (count as usize).saturating_sub(flags),
+            })
+        })
+        .collect()
+}
+
+/// When the cells of a constraint are included in another one, the cells only in the bigger
+/// one hold the difference of bombs
+fn subset_deductions(board: &Board) -> (Positions, Positions) {
+    let constraints = constraints(board);
+    let mut bombs = Vec::new();
+    let mut safe = Vec::new();
+    for small in constraints.iter() {
+        for big in constraints.iter() {
+            if small.cells.len() >= big.cells.len() || !small.cells.is_subset(&big.cells) {
+                continue;
+            }
+            let rest = big
+                .cells
+                .difference(&small.cells)
+                .copied()
+                .collect::<Vec<_>>();
+            let rest_bombs = big.bombs.saturating_sub(small.bombs);
+            if rest_bombs == 0 {
+                safe.extend(rest);
+            } else if rest_bombs == rest.len() {
+                bombs.extend(rest);
+            }
+        }
+    }
+    (bombs, safe)
+}
+
+/// Hidden cell with the lowest estimated probability of being a bomb
+fn best_guess(board: &Board) -> Option<(usize, usize)> {
+    let constraints = constraints(board);
+    let hidden = board.hidden_positions();
+    let default_probability = match board.mines_remaining {
+        Some(mines) if !hidden.is_empty() => mines.max(0) as f64 / hidden.len() as f64,
+        _ => 0.2,
+    };
+    hidden.into_iter().min_by(|&a, &b| {
+        let probability = |position| {
+            constraints
+                .iter()
+                .filter(|constraint| constraint.cells.contains(&position))
+                .map(|constraint| constraint.bombs as f64 / constraint.cells.len() as f64)
+                .fold(None, |max: Option<f64>, p| {
+                    Some(max.map_or(p, |max| max.max(p)))
+                })
+                .unwrap_or(default_probability)
+        };
+        probability(a).total_cmp(&probability(b))
+    })
+}
+
+impl Bot for SolverBot {
+    fn next_action(&mut self, board: &Board) -> Action {
+        self.next_actions(board)[0]
+    }
+
+    fn next_actions(&mut self, board: &Board) -> Vec<Action> {
+        let (mut bombs, mut safe) = (board.known_bombs(), board.known_safe());
+        if bombs.is_empty() && safe.is_empty() {
+            (bombs, safe) = subset_deductions(board);
+        }
+        let mut actions = Vec::new();
+        for (x, y) in bombs {
+            let action = Action::Flag { x, y };
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        for (x, y) in safe {
+            let action = Action::Reveal { x, y };
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        if actions.is_empty() {
+            let (x, y) = best_guess(board).unwrap_or((0, 0));
+            actions.push(Action::Reveal { x, y });
+        }
+        actions
+    }
+}
+
+fn main() {
+    run(SolverBot)
+}
@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Read, Write};
+use std::time::Duration;
+
+pub use minesweeper::MinesweeperCellType;
+
+/// Version of the JSON protocol spoken by the bots of this crate
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Reveal {
+        x: usize,
+        y: usize,
+    },
+    Flag {
+        x: usize,
+        y: usize,
+    },
+    Unflag {
+        x: usize,
+        y: usize,
+    },
+    /// Reveal the neighbours of a revealed cell whose bombs are all flagged
+    Chord {
+        x: usize,
+        y: usize,
+    },
+}
+
+/// A Minesweeper bot, give it to `run` in the `main` function of the bot
+pub trait Bot {
+    /// Choose the next action
+    fn next_action(&mut self, board: &Board) -> Action;
+
+    /// Choose several actions played in order, only `next_action` by default
+    fn next_actions(&mut self, board: &Board) -> Vec<Action> {
+        vec![self.next_action(board)]
+    }
+}
+
+/// JSON input of the protocol
+#[derive(Deserialize)]
+struct State {
+    width: usize,
+    height: usize,
+    cells: Vec<String>,
+    mines_remaining: i64,
+    #[serde(rename = "move")]
+    move_number: usize,
+    time_left_ms: Option<u64>,
+}
+
+/// The grid as seen by a bot, with only `Hidden`, `Flagged` and `Found` cells
+#[derive(Debug, Clone)]
+pub struct Board {
+    cells: Vec<Vec<MinesweeperCellType>>,
+    /// Number of bombs minus the number of flags, unknown with the console board
+    pub mines_remaining: Option<i64>,
+    /// Number of moves already played, unknown with the console board
+    pub move_number: Option<usize>,
+    /// Time left on the clock of the bot for the rest of the game
+    pub time_left: Option<Duration>,
+}
+
+impl Board {
+    /// Parse the console board, one line per line of the grid separated by `separator` with
+    /// cells separated by spaces
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper_bot_sdk::{Board, MinesweeperCellType};
+    /// let board = Board::from_console("1 _\n_ _", '\n');
+    /// assert_eq!(board.width(), 2);
+    /// assert_eq!(board.cell(0, 0), Some(MinesweeperCellType::Found(1)));
+    /// assert_eq!(board.cell(1, 1), Some(MinesweeperCellType::Hidden));
+    /// ```
+    pub fn from_console(console: &str, separator: char) -> Self {
+        let cells = console
+            .split(separator)
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|cell| match cell.parse() {
+                        Ok(bombs) => MinesweeperCellType::Found(bombs),
+                        Err(_) => MinesweeperCellType::Hidden,
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            cells,
+            mines_remaining: None,
+            move_number: None,
+            time_left: None,
+        }
+    }
+
+    /// Parse the JSON input of the protocol
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper_bot_sdk::{Board, MinesweeperCellType};
+    /// let board = Board::from_json(
+    ///     r#"{"protocol":1,"width":2,"height":2,"cells":["1F","__"],"flags":[[0,1]],"mines_remaining":0,"move":3,"time_left_ms":2000}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(board.cell(0, 1), Some(MinesweeperCellType::Flagged));
+    /// assert_eq!(board.move_number, Some(3));
+    /// ```
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let state: State = serde_json::from_str(json)?;
+        let cells = state
+            .cells
+            .iter()
+            .take(state.width)
+            .map(|line| {
+                line.chars()
+                    .take(state.height)
+                    .map(|cell| match cell {
+                        'F' => MinesweeperCellType::Flagged,
+                        '0'..='8' => MinesweeperCellType::Found(cell as u8 - b'0'),
+                        _ => MinesweeperCellType::Hidden,
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            cells,
+            mines_remaining: Some(state.mines_remaining),
+            move_number: Some(state.move_number),
+            time_left: state.time_left_ms.map(Duration::from_millis),
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.first().map_or(0, Vec::len)
+    }
+
+    pub fn cell(&self, xpos: usize, ypos: usize) -> Option<MinesweeperCellType> {
+        self.cells.get(xpos)?.get(ypos).copied()
+    }
+
+    /// Every position of the grid, line by line
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.width()).flat_map(move |xpos| (0..self.height()).map(move |ypos| (xpos, ypos)))
+    }
+
+    /// Positions of the hidden cells without flag
+    pub fn hidden_positions(&self) -> Vec<(usize, usize)> {
+        self.positions()
+            .filter(|&(xpos, ypos)| self.cell(xpos, ypos) == Some(MinesweeperCellType::Hidden))
+            .collect()
+    }
+
+    /// Positions around a position inside the grid
+    pub fn neighbours(&self, xpos: usize, ypos: usize) -> Vec<(usize, usize)> {
+        let mut neighbours = Vec::with_capacity(8);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                if x == 0 && y == 0 {
+                    continue;
+                }
+                if let (Some(neighbour_xpos), Some(neighbour_ypos)) =
+                    (xpos.checked_add_signed(x), ypos.checked_add_signed(y))
+                {
+                    if self.cell(neighbour_xpos, neighbour_ypos).is_some() {
+                        neighbours.push((neighbour_xpos, neighbour_ypos));
+                    }
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// Hidden cells that are bombs for sure, because a revealed neighbour has as many
+    /// bombs around it as hidden or flagged cells
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper_bot_sdk::Board;
+    /// let board = Board::from_console("1 _\n1 1", '\n');
+    /// assert_eq!(board.known_bombs(), vec![(0, 1)]);
+    /// ```
+    pub fn known_bombs(&self) -> Vec<(usize, usize)> {
+        let mut bombs = Vec::new();
+        for (xpos, ypos) in self.positions() {
+            let Some(MinesweeperCellType::Found(count)) = self.cell(xpos, ypos) else {
+                continue;
+            };
+            let unknown = self.unrevealed_neighbours(xpos, ypos);
+            if unknown.len() == count as usize {
+                for position in unknown {
+                    if self.cell(position.0, position.1) == Some(MinesweeperCellType::Hidden)
+                        && !bombs.contains(&position)
+                    {
+                        bombs.push(position);
+                    }
+                }
+            }
+        }
+        bombs
+    }
+
+    /// Hidden cells without bomb for sure, because a revealed neighbour already has all its
+    /// bombs flagged
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper_bot_sdk::Board;
+    /// let board = Board::from_console("0 _\n_ _", '\n');
+    /// assert_eq!(board.known_safe().len(), 3);
+    /// ```
+    pub fn known_safe(&self) -> Vec<(usize, usize)> {
+        let mut safe = Vec::new();
+        for (xpos, ypos) in self.positions() {
+            let Some(MinesweeperCellType::Found(count)) = self.cell(xpos, ypos) else {
+                continue;
+            };
+            let neighbours = self.neighbours(xpos, ypos);
+            let flags = neighbours
+                .iter()
+                .filter(|&&(x, y)| self.cell(x, y) == Some(MinesweeperCellType::Flagged))
+                .count();
+            if flags == count as usize {
+                for position in neighbours {
+                    if self.cell(position.0, position.1) == Some(MinesweeperCellType::Hidden)
+                        && !safe.contains(&position)
+                    {
+                        safe.push(position);
+                    }
+                }
+            }
+        }
+        safe
+    }
+
+    /// Hidden or flagged neighbours of a position
+    pub fn unrevealed_neighbours(&self, xpos: usize, ypos: usize) -> Vec<(usize, usize)> {
+        self.neighbours(xpos, ypos)
+            .into_iter()
+            .filter(|&(x, y)| !matches!(self.cell(x, y), Some(MinesweeperCellType::Found(_))))
+            .collect()
+    }
+}
+
+/// Play with a bot, handling the protocol on stdin and stdout for a run per move as well as
+/// for a session
+pub fn run<B: Bot>(mut bot: B) {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut stdout = io::stdout();
+    let mut declared = false;
+    let mut line = String::new();
+    while stdin.read_line(&mut line).unwrap_or(0) > 0 {
+        let input = line.trim();
+        let (board, session) = if input.starts_with('{') {
+            match Board::from_json(input) {
+                Ok(board) => (board, false),
+                Err(error) => {
+                    eprintln!("Cannot parse the board: {}", error);
+                    return;
+                }
+            }
+        } else if input.contains('/') {
+            // Each line of a session board ends with '/', even on a board of one line
+            (Board::from_console(input, '/'), true)
+        } else {
+            // The console board of a run per move spans every line of stdin
+            let mut console = line.clone();
+            let _ = stdin.read_to_string(&mut console);
+            (Board::from_console(&console, '\n'), false)
+        };
+        line.clear();
+        if board.width() == 0 {
+            continue;
+        }
+
+        if !declared {
+            declared = true;
+            let _ = writeln!(stdout, "{{\"protocol\":{}}}", PROTOCOL_VERSION);
+            // A session sends the JSON board again once the protocol is declared
+            if session {
+                let _ = stdout.flush();
+                continue;
+            }
+        }
+        let actions = bot.next_actions(&board);
+        let output = match actions.as_slice() {
+            [action] => serde_json::to_string(action),
+            _ => serde_json::to_string(&serde_json::json!({ "actions": actions })),
+        };
+        let _ = writeln!(stdout, "{}", output.unwrap_or_default());
+        let _ = stdout.flush();
+    }
+}
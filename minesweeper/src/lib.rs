@@ -1,6 +1,8 @@
 #![feature(let_chains)]
 
-use rand::random;
+use rand::distributions::Standard;
+use rand::seq::SliceRandom;
+use rand::Rng;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MinesweeperCellType {
@@ -8,6 +10,58 @@ pub enum MinesweeperCellType {
     Bomb,
     BombExploded,
     Found(u8),
+    /// A hidden cell without bomb marked with a flag
+    Flagged,
+    /// A hidden cell with a bomb marked with a flag
+    BombFlagged,
+}
+
+impl MinesweeperCellType {
+    pub fn is_bomb(self) -> bool {
+        matches!(
+            self,
+            MinesweeperCellType::Bomb
+                | MinesweeperCellType::BombExploded
+                | MinesweeperCellType::BombFlagged
+        )
+    }
+
+    pub fn is_flagged(self) -> bool {
+        matches!(
+            self,
+            MinesweeperCellType::Flagged | MinesweeperCellType::BombFlagged
+        )
+    }
+
+    /// Return the cell as seen by a player, who cannot know where the bombs are
+    pub fn hide_bomb(self) -> Self {
+        match self {
+            MinesweeperCellType::Bomb => MinesweeperCellType::Hidden,
+            MinesweeperCellType::BombFlagged => MinesweeperCellType::Flagged,
+            cell => cell,
+        }
+    }
+
+    pub fn to_discord_emoji(self) -> &'static str {
+        match self {
+            MinesweeperCellType::Hidden => "🟫",
+            MinesweeperCellType::Bomb => "💣",
+            MinesweeperCellType::BombExploded => "🧨",
+            MinesweeperCellType::Flagged | MinesweeperCellType::BombFlagged => "🚩",
+            MinesweeperCellType::Found(n) => match n {
+                0 => "0️⃣",
+                1 => "1️⃣",
+                2 => "2️⃣",
+                3 => "3️⃣",
+                4 => "4️⃣",
+                5 => "5️⃣",
+                6 => "6️⃣",
+                7 => "7️⃣",
+                8 => "8️⃣",
+                _ => unreachable!(),
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -22,12 +76,33 @@ impl MinesweeperGrid {
     /// let mut grid = MinesweeperGrid::new(10, 10, 0.3);
     /// ```
     pub fn new(width: usize, height: usize, bomb_probability: f64) -> Self {
+        Self::with_rng(width, height, bomb_probability, &mut rand::thread_rng())
+    }
+
+    /// Create a new MinesweeperGrid whose bombs are drawn from `rng`, a seeded generator
+    /// giving the same grid each time
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// # use rand::rngs::StdRng;
+    /// # use rand::SeedableRng;
+    /// let grid = MinesweeperGrid::with_rng(10, 10, 0.3, &mut StdRng::seed_from_u64(42));
+    /// let same = MinesweeperGrid::with_rng(10, 10, 0.3, &mut StdRng::seed_from_u64(42));
+    /// assert_eq!(grid.0, same.0);
+    /// ```
+    pub fn with_rng<R: Rng + ?Sized>(
+        width: usize,
+        height: usize,
+        bomb_probability: f64,
+        rng: &mut R,
+    ) -> Self {
         Self(
             (0..width)
                 .map(|_| {
                     (0..height)
                         .map(|_| {
-                            if random::<f64>() > bomb_probability {
+                            if rng.sample::<f64, _>(Standard) > bomb_probability {
                                 MinesweeperCellType::Hidden
                             } else {
                                 MinesweeperCellType::Bomb
@@ -48,7 +123,9 @@ impl MinesweeperGrid {
                     .map(|cell| match cell {
                         MinesweeperCellType::Hidden
                         | MinesweeperCellType::Bomb
-                        | MinesweeperCellType::BombExploded => "_".to_string(),
+                        | MinesweeperCellType::BombExploded
+                        | MinesweeperCellType::Flagged
+                        | MinesweeperCellType::BombFlagged => "_".to_string(),
                         MinesweeperCellType::Found(n) => n.to_string(),
                     })
                     .collect::<Vec<String>>()
@@ -58,28 +135,283 @@ impl MinesweeperGrid {
             .join("\n")
     }
 
+    /// Return a copy of the grid as seen by a player, where bombs look like hidden cells
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::{MinesweeperCellType, MinesweeperGrid};
+    /// let grid = MinesweeperGrid::new(10, 10, 1.0);
+    /// assert_eq!(grid.player_view().get_cell(0, 0), Some(&MinesweeperCellType::Hidden));
+    /// ```
+    pub fn player_view(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|column| column.iter().map(|cell| cell.hide_bomb()).collect())
+                .collect(),
+        )
+    }
+
+    /// Number of columns of the grid
+    pub fn width(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Number of rows of the grid
+    pub fn height(&self) -> usize {
+        self.0.first().map_or(0, |column| column.len())
+    }
+
+    /// Return the first position of a `width` x `height` window centred on a position
+    ///
+    /// The window is moved so that it stays inside the grid when possible
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let grid = MinesweeperGrid::new(30, 30, 0.3);
+    /// assert_eq!(grid.window_around(0, 15, 12, 12), (0, 9));
+    /// assert_eq!(grid.window_around(29, 29, 12, 12), (18, 18));
+    /// ```
+    pub fn window_around(
+        &self,
+        xpos: usize,
+        ypos: usize,
+        width: usize,
+        height: usize,
+    ) -> (usize, usize) {
+        let xstart = xpos
+            .saturating_sub(width / 2)
+            .min(self.width().saturating_sub(width));
+        let ystart = ypos
+            .saturating_sub(height / 2)
+            .min(self.height().saturating_sub(height));
+        (xstart, ystart)
+    }
+
     pub fn to_discord_string(&self) -> String {
+        self.to_discord_string_window(0, 0, self.width(), self.height())
+    }
+
+    /// Same as `to_discord_string` but only for the cells of a `width` x `height` window
+    /// starting at a position, the window is cut at the borders of the grid
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let grid = MinesweeperGrid::new(30, 30, 0.0);
+    /// let window = grid.to_discord_string_window(25, 0, 12, 12);
+    /// assert_eq!(window.lines().count(), 5);
+    /// ```
+    pub fn to_discord_string_window(
+        &self,
+        xstart: usize,
+        ystart: usize,
+        width: usize,
+        height: usize,
+    ) -> String {
         self.0
             .iter()
+            .skip(xstart)
+            .take(width)
             .map(|column| {
                 column
                     .iter()
-                    .map(|cell| match cell {
-                        MinesweeperCellType::Hidden => "🟫".to_string(),
-                        MinesweeperCellType::Bomb => "💣".to_string(),
-                        MinesweeperCellType::BombExploded => "🧨".to_string(),
-                        MinesweeperCellType::Found(n) => match n {
-                            0 => "0️⃣".to_string(),
-                            1 => "1️⃣".to_string(),
-                            2 => "2️⃣".to_string(),
-                            3 => "3️⃣".to_string(),
-                            4 => "4️⃣".to_string(),
-                            5 => "5️⃣".to_string(),
-                            6 => "6️⃣".to_string(),
-                            7 => "7️⃣".to_string(),
-                            8 => "8️⃣".to_string(),
+                    .skip(ystart)
+                    .take(height)
+                    .map(|cell| cell.to_discord_emoji())
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn to_discord_ansi_string(&self) -> String {
+        self.to_discord_ansi_string_window(0, 0, self.width(), self.height())
+    }
+
+    /// Render a window of the grid inside an ```` ```ansi ```` code block, with one character
+    /// per cell, the classic colour of each number and the positions on the axes
+    ///
+    /// Colours are only written when they change, so the block stays far smaller than
+    /// `to_discord_string_window` for the same window, but a window whose colours change at every
+    /// cell can still be longer than a Discord message : the length of the block should be
+    /// checked before sending it
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let grid = MinesweeperGrid::new(24, 24, 0.3);
+    /// let block = grid.to_discord_ansi_string_window(0, 0, 24, 24);
+    /// assert!(block.starts_with("```ansi\n"));
+    /// assert!(block.ends_with("```"));
+    /// ```
+    pub fn to_discord_ansi_string_window(
+        &self,
+        xstart: usize,
+        ystart: usize,
+        width: usize,
+        height: usize,
+    ) -> String {
+        let xend = (xstart + width).min(self.width());
+        let yend = (ystart + height).min(self.height());
+        let label_width = xend.saturating_sub(1).to_string().len();
+
+        let mut block = String::from("```ansi\n");
+        if yend > 10 {
+            block.push_str(&" ".repeat(label_width + 1));
+            for ypos in ystart..yend {
+                if ypos % 10 == 0 || ypos == ystart {
+                    block.push_str(&(ypos / 10 % 10).to_string());
+                } else {
+                    block.push(' ');
+                }
+            }
+            block.push('\n');
+        }
+        block.push_str(&" ".repeat(label_width + 1));
+        for ypos in ystart..yend {
+            block.push_str(&(ypos % 10).to_string());
+        }
+        block.push('\n');
+
+        for (xpos, column) in self.0.iter().enumerate().take(xend).skip(xstart) {
+            block.push_str(&format!("{:>label_width$} ", xpos));
+            let mut current_colour = None;
+            for cell in column.iter().take(yend).skip(ystart) {
+                let (character, colour) = match cell {
+                    MinesweeperCellType::Hidden => ('#', None),
+                    MinesweeperCellType::Bomb => ('*', None),
+                    MinesweeperCellType::BombExploded => ('X', Some("1;31")),
+                    MinesweeperCellType::Flagged | MinesweeperCellType::BombFlagged => {
+                        ('F', Some("1;33"))
+                    }
+                    MinesweeperCellType::Found(0) => ('.', None),
+                    MinesweeperCellType::Found(n) => (
+                        char::from(b'0' + n),
+                        Some(match n {
+                            1 => "34",
+                            2 => "32",
+                            3 => "31",
+                            4 => "35",
+                            5 => "33",
+                            6 => "36",
+                            7 => "37",
+                            8 => "30",
                             _ => unreachable!(),
-                        },
+                        }),
+                    ),
+                };
+                if colour != current_colour {
+                    block.push_str(&format!("\u{1b}[{}m", colour.unwrap_or("0")));
+                    current_colour = colour;
+                }
+                block.push(character);
+            }
+            if current_colour.is_some() {
+                block.push_str("\u{1b}[0m");
+            }
+            block.push('\n');
+        }
+        block.push_str("```");
+        block
+    }
+
+    /// Render a scaled overview of the grid inside an ```` ```ansi ```` code block, at most
+    /// `size` characters per side, with the blocks of cells inside a window highlighted
+    ///
+    /// Each character sums up a square block of cells : `#` when they are all hidden, `.` when
+    /// they are all revealed, `+` when some are revealed and `X` when one is an exploded bomb
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let grid = MinesweeperGrid::new(96, 64, 0.3);
+    /// let minimap = grid.to_minimap_string(16, (0, 0, 12, 12));
+    /// assert_eq!(minimap.lines().count(), 16 + 2);
+    /// assert!(minimap.lines().nth(1).unwrap().contains("\u{1b}[47;30m##"));
+    /// ```
+    pub fn to_minimap_string(
+        &self,
+        size: usize,
+        (xstart, ystart, width, height): (usize, usize, usize, usize),
+    ) -> String {
+        let scale = self.width().max(self.height()).div_ceil(size.max(1)).max(1);
+        let mut block = String::from("```ansi\n");
+        for xblock in (0..self.width()).step_by(scale) {
+            let xend = (xblock + scale).min(self.width());
+            let mut highlighted = false;
+            for yblock in (0..self.height()).step_by(scale) {
+                let yend = (yblock + scale).min(self.height());
+                let cells = self.0[xblock..xend]
+                    .iter()
+                    .flat_map(|column| &column[yblock..yend]);
+                let (mut revealed, mut total, mut exploded) = (0, 0, false);
+                for cell in cells {
+                    total += 1;
+                    match cell {
+                        MinesweeperCellType::Found(_) => revealed += 1,
+                        MinesweeperCellType::BombExploded => exploded = true,
+                        _ => (),
+                    }
+                }
+                let in_window = xblock < xstart + width
+                    && xend > xstart
+                    && yblock < ystart + height
+                    && yend > ystart;
+                if in_window != highlighted {
+                    block.push_str(if in_window {
+                        "\u{1b}[47;30m"
+                    } else {
+                        "\u{1b}[0m"
+                    });
+                    highlighted = in_window;
+                }
+                block.push(if exploded {
+                    'X'
+                } else if revealed == 0 {
+                    '#'
+                } else if revealed == total {
+                    '.'
+                } else {
+                    '+'
+                });
+            }
+            if highlighted {
+                block.push_str("\u{1b}[0m");
+            }
+            block.push('\n');
+        }
+        block.push_str("```");
+        block
+    }
+
+    /// Render the whole grid as a spoiler-tag Minesweeper: every cell not revealed yet is
+    /// wrapped in `||…||` and shows its number or its bomb once clicked
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let grid = MinesweeperGrid::new(3, 3, 1.0);
+    /// assert_eq!(grid.to_discord_spoiler_string().matches("||💣||").count(), 9);
+    /// ```
+    pub fn to_discord_spoiler_string(&self) -> String {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(xpos, column)| {
+                column
+                    .iter()
+                    .enumerate()
+                    .map(|(ypos, cell)| match cell {
+                        MinesweeperCellType::Found(_) => cell.to_discord_emoji().to_string(),
+                        cell if cell.is_bomb() => "||💣||".to_string(),
+                        _ => format!(
+                            "||{}||",
+                            MinesweeperCellType::Found(self.count_neighbour_bombs(xpos, ypos))
+                                .to_discord_emoji()
+                        ),
                     })
                     .collect::<Vec<String>>()
                     .join(" ")
@@ -88,6 +420,49 @@ impl MinesweeperGrid {
             .join("\n")
     }
 
+    /// Return the number of bombs of the grid
+    pub fn bomb_count(&self) -> usize {
+        self.0
+            .iter()
+            .map(|column| column.iter().filter(|cell| cell.is_bomb()).count())
+            .sum()
+    }
+
+    /// Return the number of bombs around a position
+    pub fn count_neighbour_bombs(&self, xpos: usize, ypos: usize) -> u8 {
+        self.get_neighbours(xpos, ypos)
+            .iter()
+            .filter(|(cell, _)| cell.is_bomb())
+            .count() as u8
+    }
+
+    /// Return a random hidden position without bomb, preferably with no bomb around it so
+    /// that discovering it opens a zone
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let mut grid = MinesweeperGrid::new(10, 10, 0.2);
+    /// if let Some((xpos, ypos)) = grid.find_safe_opening(&mut rand::thread_rng()) {
+    ///     assert!(grid.discover(xpos, ypos).is_some());
+    /// }
+    /// ```
+    pub fn find_safe_opening<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, usize)> {
+        let safe_positions = (0..self.width())
+            .flat_map(|xpos| (0..self.height()).map(move |ypos| (xpos, ypos)))
+            .filter(|&(xpos, ypos)| self.get_cell(xpos, ypos) == Some(&MinesweeperCellType::Hidden))
+            .collect::<Vec<_>>();
+        let openings = safe_positions
+            .iter()
+            .copied()
+            .filter(|&(xpos, ypos)| self.count_neighbour_bombs(xpos, ypos) == 0)
+            .collect::<Vec<_>>();
+        openings
+            .choose(rng)
+            .or_else(|| safe_positions.choose(rng))
+            .copied()
+    }
+
     fn reveal_zone(&mut self, xpos: usize, ypos: usize) -> usize {
         let mut revealed_cell = 0;
 
@@ -95,7 +470,7 @@ impl MinesweeperGrid {
 
         let neighbours_bomb = neighbours
             .iter()
-            .filter(|(cell, _)| *cell == MinesweeperCellType::Bomb)
+            .filter(|(cell, _)| cell.is_bomb())
             .count() as u8;
 
         if let Some(cell) = self.get_mut_cell(xpos, ypos) && *cell == MinesweeperCellType::Hidden {
@@ -148,7 +523,93 @@ impl MinesweeperGrid {
             }
             MinesweeperCellType::BombExploded => None,
             MinesweeperCellType::Found(_) => None,
+            MinesweeperCellType::Flagged | MinesweeperCellType::BombFlagged => None,
+        }
+    }
+
+    /// Put or remove a flag on a hidden cell and return whether the cell is now flagged
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// # let mut grid = MinesweeperGrid::new(10, 10, 0.3);
+    /// assert_eq!(grid.toggle_flag(5, 5), Some(true));
+    /// assert_eq!(grid.discover(5, 5), None);
+    /// assert_eq!(grid.toggle_flag(5, 5), Some(false));
+    /// ```
+    /// Return `None` if the position is already revealed or invalid
+    pub fn toggle_flag(&mut self, xpos: usize, ypos: usize) -> Option<bool> {
+        let cell = self.get_mut_cell(xpos, ypos)?;
+        *cell = match *cell {
+            MinesweeperCellType::Hidden => MinesweeperCellType::Flagged,
+            MinesweeperCellType::Bomb => MinesweeperCellType::BombFlagged,
+            MinesweeperCellType::Flagged => MinesweeperCellType::Hidden,
+            MinesweeperCellType::BombFlagged => MinesweeperCellType::Bomb,
+            MinesweeperCellType::BombExploded | MinesweeperCellType::Found(_) => return None,
+        };
+        Some(cell.is_flagged())
+    }
+
+    /// Reveal the hidden neighbours of a revealed cell having as many flagged neighbours as
+    /// neighbour bombs, and return the number of cells revealed
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let mut grid = MinesweeperGrid::new(10, 10, 0.0);
+    /// assert_eq!(grid.chord(5, 5), Some(0));
+    /// grid.discover(5, 5);
+    /// assert_eq!(grid.chord(5, 5), Some(0));
+    /// ```
+    /// Return `None` if a bomb is revealed because a flag was wrong
+    pub fn chord(&mut self, xpos: usize, ypos: usize) -> Option<usize> {
+        let Some(&MinesweeperCellType::Found(bombs)) = self.get_cell(xpos, ypos) else {
+            return Some(0);
+        };
+        let neighbours = self.get_neighbours(xpos, ypos);
+        if neighbours
+            .iter()
+            .filter(|(cell, _)| cell.is_flagged())
+            .count()
+            != bombs as usize
+        {
+            return Some(0);
+        }
+
+        let mut revealed = 0;
+        for (_, (neighbour_xpos, neighbour_ypos)) in neighbours {
+            // Zones revealed by the previous neighbours may already contain this one
+            if matches!(
+                self.get_cell(neighbour_xpos, neighbour_ypos),
+                Some(MinesweeperCellType::Hidden | MinesweeperCellType::Bomb)
+            ) {
+                revealed += self.discover(neighbour_xpos, neighbour_ypos)?;
+            }
         }
+        Some(revealed)
+    }
+
+    /// Return true when every cell without bomb has been revealed
+    ///
+    /// Example :
+    /// ```rust
+    /// # use minesweeper::MinesweeperGrid;
+    /// let mut grid = MinesweeperGrid::new(10, 10, 0.0);
+    /// assert!(!grid.is_won());
+    /// grid.discover(5, 5);
+    /// assert!(grid.is_won());
+    /// ```
+    pub fn is_won(&self) -> bool {
+        self.0.iter().all(|column| {
+            column.iter().all(|cell| {
+                matches!(
+                    cell,
+                    MinesweeperCellType::Found(_)
+                        | MinesweeperCellType::Bomb
+                        | MinesweeperCellType::BombFlagged
+                )
+            })
+        })
     }
 
     fn get_neighbours(
@@ -175,7 +636,7 @@ impl MinesweeperGrid {
         v
     }
 
-    fn get_cell(&self, xpos: usize, ypos: usize) -> Option<&MinesweeperCellType> {
+    pub fn get_cell(&self, xpos: usize, ypos: usize) -> Option<&MinesweeperCellType> {
         self.0.get(xpos)?.get(ypos)
     }
 
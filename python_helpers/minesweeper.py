@@ -0,0 +1,173 @@
+"""Helpers for Minesweeper bots written in Python, available with `import minesweeper`
+
+A whole bot can be:
+
+    import minesweeper
+
+    minesweeper.play(minesweeper.solve)
+
+`play` reads the boards, calls the strategy with a `Board` and writes the actions it returns,
+for a run per move as well as in a session.
+"""
+
+import json
+import random
+import sys
+
+PROTOCOL_VERSION = 1
+FLAG = "F"
+
+
+class Board:
+    """The grid as seen by a bot, each cell is `None` when hidden, `FLAG` or the number of
+    neighbour bombs"""
+
+    def __init__(self, cells, mines_remaining=None, move=None, time_left_ms=None):
+        self.cells = cells
+        self.mines_remaining = mines_remaining
+        self.move = move
+        self.time_left_ms = time_left_ms
+
+    @classmethod
+    def from_console(cls, text, separator="\n"):
+        cells = [
+            [int(cell) if cell.isdigit() else None for cell in line.split()]
+            for line in text.split(separator)
+            if line.strip()
+        ]
+        return cls(cells)
+
+    @classmethod
+    def from_json(cls, text):
+        state = json.loads(text)
+        cells = [
+            [
+                FLAG if cell == FLAG else int(cell) if cell.isdigit() else None
+                for cell in line
+            ]
+            for line in state["cells"]
+        ]
+        return cls(
+            cells, state.get("mines_remaining"), state.get("move"), state.get("time_left_ms")
+        )
+
+    @property
+    def width(self):
+        return len(self.cells)
+
+    @property
+    def height(self):
+        return len(self.cells[0]) if self.cells else 0
+
+    def cell(self, x, y):
+        return self.cells[x][y]
+
+    def positions(self):
+        return [(x, y) for x in range(self.width) for y in range(self.height)]
+
+    def hidden(self):
+        """Positions of the hidden cells without flag"""
+        return [(x, y) for (x, y) in self.positions() if self.cells[x][y] is None]
+
+    def neighbours(self, x, y):
+        return [
+            (x + dx, y + dy)
+            for dx in (-1, 0, 1)
+            for dy in (-1, 0, 1)
+            if (dx, dy) != (0, 0)
+            and 0 <= x + dx < self.width
+            and 0 <= y + dy < self.height
+        ]
+
+    def unrevealed_neighbours(self, x, y):
+        """Hidden or flagged neighbours of a position"""
+        return [
+            (nx, ny) for (nx, ny) in self.neighbours(x, y) if not isinstance(self.cells[nx][ny], int)
+        ]
+
+    def known_bombs(self):
+        """Hidden cells that are bombs for sure"""
+        bombs = set()
+        for x, y in self.positions():
+            count = self.cells[x][y]
+            if isinstance(count, int):
+                unknown = self.unrevealed_neighbours(x, y)
+                if len(unknown) == count:
+                    bombs.update(p for p in unknown if self.cells[p[0]][p[1]] is None)
+        return sorted(bombs)
+
+    def known_safe(self):
+        """Hidden cells without bomb for sure, all the bombs around a neighbour being flagged"""
+        safe = set()
+        for x, y in self.positions():
+            count = self.cells[x][y]
+            if isinstance(count, int):
+                neighbours = self.neighbours(x, y)
+                flags = sum(1 for (nx, ny) in neighbours if self.cells[nx][ny] == FLAG)
+                if flags == count:
+                    safe.update(p for p in neighbours if self.cells[p[0]][p[1]] is None)
+        return sorted(safe)
+
+
+def reveal(x, y):
+    return {"action": "reveal", "x": x, "y": y}
+
+
+def flag(x, y):
+    return {"action": "flag", "x": x, "y": y}
+
+
+def unflag(x, y):
+    return {"action": "unflag", "x": x, "y": y}
+
+
+def chord(x, y):
+    return {"action": "chord", "x": x, "y": y}
+
+
+def output(actions):
+    """Write one action or a list of actions as the move of the bot"""
+    if isinstance(actions, dict):
+        actions = [actions]
+    line = json.dumps(actions[0] if len(actions) == 1 else {"actions": actions})
+    print(line, flush=True)
+
+
+def solve(board):
+    """Flag the known bombs and reveal the known safe cells, or guess a random hidden cell"""
+    actions = [flag(x, y) for (x, y) in board.known_bombs()]
+    actions += [reveal(x, y) for (x, y) in board.known_safe()]
+    if not actions:
+        x, y = random.choice(board.hidden() or [(0, 0)])
+        actions.append(reveal(x, y))
+    return actions
+
+
+def play(strategy):
+    """Play with `strategy`, a function returning the actions for a `Board`"""
+    declared = False
+    while True:
+        line = sys.stdin.readline()
+        if not line:
+            return
+        line = line.strip()
+        if not line:
+            continue
+        session = False
+        if line.startswith("{"):
+            board = Board.from_json(line)
+        elif "/" in line:
+            # Each line of a session board ends with "/", even on a board of one line
+            board = Board.from_console(line, "/")
+            session = True
+        else:
+            # The console board of a run per move spans every line of stdin
+            board = Board.from_console(line + "\n" + sys.stdin.read())
+
+        if not declared:
+            declared = True
+            print(json.dumps({"protocol": PROTOCOL_VERSION}), flush=True)
+            # A session sends the JSON board again once the protocol is declared
+            if session:
+                continue
+        output(strategy(board))
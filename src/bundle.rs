@@ -0,0 +1,300 @@
+use crate::runner::snapshot::{SNAPSHOT_FILE, SNAPSHOT_TMP_FILE};
+use eyre::Error;
+use flate2::read::DeflateDecoder;
+use serde::Deserialize;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tracing::instrument;
+
+/// First bytes of a zip file
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// File of a bundle naming its entry point
+pub const MANIFEST_FILE: &str = "bot.toml";
+/// Limits of an extracted bundle, against zip bombs
+pub const MAX_BUNDLE_FILES: usize = 256;
+pub const MAX_BUNDLE_SIZE: u64 = 64 * 1024 * 1024;
+/// Files written next to the bot, a bundle cannot hold them
+const RESERVED_FILES: [&str; 2] = [SNAPSHOT_FILE, SNAPSHOT_TMP_FILE];
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+
+/// `bot.toml` at the root of a bundle
+#[derive(Deserialize)]
+struct Manifest {
+    /// File run by the language of its extension, at the root of the bundle
+    entry: String,
+}
+
+/// Files of a zip bundle, with paths relative to its root
+pub struct Bundle {
+    pub entry: PathBuf,
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Read the files of a zip bundle and its manifest, only stored and deflated files are
+/// supported
+#[instrument(skip(bytes))]
+pub fn read_bundle(bytes: &[u8]) -> eyre::Result<Bundle> {
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| read_u32(bytes, offset) == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or(Error::msg("Bad zip file, its central directory is missing"))?;
+    let count = read_u16(bytes, end + 10).unwrap_or_default() as usize;
+    if count > MAX_BUNDLE_FILES {
+        return Err(Error::msg(format!(
+            "Too many files in the bundle ({}>{})",
+            count, MAX_BUNDLE_FILES
+        )));
+    }
+
+    let mut offset = read_u32(bytes, end + 16).unwrap_or_default() as usize;
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::with_capacity(count);
+    let mut total_size = 0;
+    for _ in 0..count {
+        let header = bytes
+            .get(offset..offset + 46)
+            .filter(|_| read_u32(bytes, offset) == Some(CENTRAL_DIRECTORY_HEADER))
+            .ok_or(Error::msg("Bad zip file, truncated central directory"))?;
+        let flags = read_u16(header, 8).unwrap_or_default();
+        let method = read_u16(header, 10).unwrap_or_default();
+        let compressed_size = read_u32(header, 20).unwrap_or_default() as usize;
+        let name_len = read_u16(header, 28).unwrap_or_default() as usize;
+        let extra_len = read_u16(header, 30).unwrap_or_default() as usize;
+        let comment_len = read_u16(header, 32).unwrap_or_default() as usize;
+        let local_offset = read_u32(header, 42).unwrap_or_default() as usize;
+        let name = bytes
+            .get(offset + 46..offset + 46 + name_len)
+            .map(String::from_utf8_lossy)
+            .ok_or(Error::msg("Bad zip file, truncated file name"))?
+            .into_owned();
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(Error::msg(format!("{} is encrypted", name)));
+        }
+        let path = PathBuf::from(&name);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::msg(format!("Bad path {} in the bundle", name)));
+        }
+        if RESERVED_FILES
+            .iter()
+            .any(|reserved| path == Path::new(reserved))
+        {
+            return Err(Error::msg(format!("{} is a reserved name", name)));
+        }
+        // A path cannot be both a file and a folder, nor be extracted twice
+        if files
+            .iter()
+            .any(|(other, _)| other.starts_with(&path) || path.starts_with(other))
+        {
+            return Err(Error::msg(format!(
+                "{} conflicts with another file of the bundle",
+                name
+            )));
+        }
+
+        let local_name_len = read_u16(bytes, local_offset + 26).unwrap_or_default() as usize;
+        let local_extra_len = read_u16(bytes, local_offset + 28).unwrap_or_default() as usize;
+        let data_start = local_offset + 30 + local_name_len + local_extra_len;
+        let data = bytes
+            .get(data_start..data_start + compressed_size)
+            .filter(|_| read_u32(bytes, local_offset) == Some(LOCAL_FILE_HEADER))
+            .ok_or(Error::msg(format!("Bad zip file, {} is truncated", name)))?;
+
+        // The sizes of the headers are not trusted, the reads stop at the remaining size
+        let remaining = MAX_BUNDLE_SIZE - total_size;
+        let mut content = Vec::new();
+        match method {
+            0 => data.take(remaining + 1).read_to_end(&mut content)?,
+            8 => DeflateDecoder::new(data)
+                .take(remaining + 1)
+                .read_to_end(&mut content)?,
+            _ => {
+                return Err(Error::msg(format!(
+                    "{} uses the unsupported compression method {}",
+                    name, method
+                )))
+            }
+        };
+        total_size += content.len() as u64;
+        if total_size > MAX_BUNDLE_SIZE {
+            return Err(Error::msg(format!(
+                "Bundle too big once extracted (>{}MB)",
+                MAX_BUNDLE_SIZE / 1024 / 1024
+            )));
+        }
+        files.push((path, content));
+    }
+
+    let (_, manifest) = files
+        .iter()
+        .find(|(path, _)| path == Path::new(MANIFEST_FILE))
+        .ok_or(Error::msg(format!(
+            "The bundle has no {} naming its entry point",
+            MANIFEST_FILE
+        )))?;
+    let manifest: Manifest = toml::from_str(&String::from_utf8_lossy(manifest))
+        .map_err(|error| Error::msg(format!("Bad {}: {}", MANIFEST_FILE, error)))?;
+    let entry = PathBuf::from(&manifest.entry);
+    if entry.components().count() != 1 || !files.iter().any(|(path, _)| *path == entry) {
+        return Err(Error::msg(format!(
+            "The entry point {} is not a file at the root of the bundle",
+            manifest.entry
+        )));
+    }
+    Ok(Bundle { entry, files })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const MANIFEST: (&str, &[u8]) = ("bot.toml", b"entry = \"main.py\"");
+
+    /// Zip file of stored entries, and deflated ones when `deflate` is set
+    fn zip(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut directory = Vec::new();
+        for (name, content) in files {
+            let (method, data) = if deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(content).unwrap();
+                (8u16, encoder.finish().unwrap())
+            } else {
+                (0u16, content.to_vec())
+            };
+            let offset = bytes.len() as u32;
+            bytes.extend(LOCAL_FILE_HEADER.to_le_bytes());
+            bytes.extend([20, 0, 0, 0]);
+            bytes.extend(method.to_le_bytes());
+            bytes.extend([0; 8]);
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend((content.len() as u32).to_le_bytes());
+            bytes.extend((name.len() as u16).to_le_bytes());
+            bytes.extend([0, 0]);
+            bytes.extend(name.as_bytes());
+            bytes.extend(&data);
+
+            directory.extend(CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+            directory.extend([20, 0, 20, 0, 0, 0]);
+            directory.extend(method.to_le_bytes());
+            directory.extend([0; 8]);
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((content.len() as u32).to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = bytes.len() as u32;
+        bytes.extend(&directory);
+        bytes.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend((files.len() as u16).to_le_bytes());
+        bytes.extend((files.len() as u16).to_le_bytes());
+        bytes.extend((directory.len() as u32).to_le_bytes());
+        bytes.extend(directory_offset.to_le_bytes());
+        bytes.extend([0, 0]);
+        bytes
+    }
+
+    fn error(files: &[(&str, &[u8])]) -> String {
+        match read_bundle(&zip(files, false)) {
+            Ok(_) => panic!("the bundle was accepted"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn stored_and_deflated_files_are_read() {
+        for deflate in [false, true] {
+            let files = [
+                MANIFEST,
+                ("main.py", b"print('(0,0)')".as_slice()),
+                ("lib/table.txt", &[7; 1000]),
+            ];
+            let bundle = read_bundle(&zip(&files, deflate)).unwrap();
+            assert_eq!(bundle.entry, PathBuf::from("main.py"));
+            assert_eq!(bundle.files.len(), 3);
+            assert_eq!(bundle.files[2].0, PathBuf::from("lib/table.txt"));
+            assert_eq!(bundle.files[2].1, vec![7; 1000]);
+        }
+    }
+
+    #[test]
+    fn bad_paths_are_rejected() {
+        for name in [
+            "../main.py",
+            "lib/../../main.py",
+            "/etc/main.py",
+            "./main.py",
+        ] {
+            let message = error(&[MANIFEST, ("main.py", b""), (name, b"")]);
+            assert!(message.starts_with("Bad path"), "{}: {}", name, message);
+        }
+    }
+
+    #[test]
+    fn reserved_and_conflicting_paths_are_rejected() {
+        for name in [SNAPSHOT_FILE, SNAPSHOT_TMP_FILE] {
+            let message = error(&[MANIFEST, ("main.py", b""), (name, b"")]);
+            assert!(message.contains("reserved"), "{}", message);
+        }
+        let message = error(&[MANIFEST, ("main.py", b""), ("main.py", b"")]);
+        assert!(message.contains("conflicts"), "{}", message);
+        let message = error(&[MANIFEST, ("main.py", b""), ("lib", b""), ("lib/a.py", b"")]);
+        assert!(message.contains("conflicts"), "{}", message);
+        let message = error(&[MANIFEST, ("main.py", b""), ("lib/a.py", b""), ("lib", b"")]);
+        assert!(message.contains("conflicts"), "{}", message);
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let names: Vec<String> = (0..=MAX_BUNDLE_FILES)
+            .map(|i| format!("{}.py", i))
+            .collect();
+        let files: Vec<(&str, &[u8])> =
+            names.iter().map(|name| (name.as_str(), &b""[..])).collect();
+        assert!(error(&files).starts_with("Too many files"));
+
+        // A deflated file bigger than the limit once extracted
+        let big = vec![0; MAX_BUNDLE_SIZE as usize + 1];
+        let bytes = zip(&[MANIFEST, ("main.py", b""), ("big", &big)], true);
+        assert!(bytes.len() < 1024 * 1024);
+        let message = read_bundle(&bytes).err().unwrap().to_string();
+        assert!(message.starts_with("Bundle too big"), "{}", message);
+    }
+
+    #[test]
+    fn manifest_must_name_a_root_file() {
+        assert!(error(&[("main.py", b"")]).contains("no bot.toml"));
+        let message = error(&[
+            ("bot.toml", b"entry = \"lib/main.py\""),
+            ("lib/main.py", b""),
+        ]);
+        assert!(message.contains("not a file at the root"), "{}", message);
+        let message = error(&[MANIFEST]);
+        assert!(message.contains("not a file at the root"), "{}", message);
+    }
+}
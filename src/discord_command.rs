@@ -1,20 +1,135 @@
 pub mod buttons;
+pub mod scratch;
+pub mod spoiler;
 pub mod start;
 
+use crate::runner::determinism::MAX_SEED;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::command::CommandOptionType;
 
 pub fn create_command(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command
+    create_grid_options(command)
         .name("start")
-        .description("Play Minesweeper with a .wasm file")
+        .description("Play Minesweeper yourself or with a .wasm file")
         .create_option(|option| {
             option
                 .name("attachment")
-                .description("A wasm file")
-                .required(true)
+                .description("A wasm file, leave empty to play yourself")
+                .required(false)
                 .kind(CommandOptionType::Attachment)
         })
+        .create_option(|option| {
+            option
+                .name("renderer")
+                .description("How the Minesweeper grid is drawn")
+                .required(false)
+                .kind(CommandOptionType::String)
+                .add_string_choice("Emoji", "emoji")
+                .add_string_choice("Compact (ANSI)", "ansi")
+        })
+        .create_option(|option| {
+            option
+                .name("session")
+                .description("Keep the bot running between moves, it reads one board per line")
+                .required(false)
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option
+                .name("scratch")
+                .description("Give the bot a /scratch directory kept between games")
+                .required(false)
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option
+                .name("seed")
+                .description("Seed of the random bytes given to the bot, to reproduce its moves")
+                .required(false)
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(MAX_SEED)
+        })
+        .create_option(|option| {
+            option
+                .name("strikes")
+                .description(
+                    "Failed turns (timeouts, crashes, invalid moves) before the bot forfeits",
+                )
+                .required(false)
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(10)
+        })
+        .create_option(|option| {
+            option
+                .name("penalty")
+                .description("What happens to a failed turn")
+                .required(false)
+                .kind(CommandOptionType::String)
+                .add_string_choice("Skip the turn", "skip")
+                .add_string_choice("Reveal a random safe cell", "reveal")
+        })
+        .create_option(|option| {
+            option
+                .name("time")
+                .description("Seconds of thinking time of the bot for the whole game")
+                .required(false)
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(3600)
+        })
+        .create_option(|option| {
+            option
+                .name("increment")
+                .description("Milliseconds added to the time of the bot after each move")
+                .required(false)
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(60_000)
+        })
+}
+
+pub fn create_scratch_command(
+    command: &mut CreateApplicationCommand,
+) -> &mut CreateApplicationCommand {
+    command
+        .name("scratch")
+        .description("Show or wipe the /scratch directory of one of your bots")
+        .create_option(|option| {
+            option
+                .name("bot")
+                .description("File name of the bot, like solver.wasm")
+                .required(true)
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option
+                .name("wipe")
+                .description("Remove everything the bot kept")
+                .required(false)
+                .kind(CommandOptionType::Boolean)
+        })
+}
+
+pub fn create_spoiler_command(
+    command: &mut CreateApplicationCommand,
+) -> &mut CreateApplicationCommand {
+    create_grid_options(command)
+        .name("spoiler")
+        .description("Generate a Minesweeper to play by clicking on spoilers")
+        .create_option(|option| {
+            option
+                .name("opening")
+                .description("Reveal a safe zone to start from (default: true)")
+                .required(false)
+                .kind(CommandOptionType::Boolean)
+        })
+}
+
+/// Options describing the Minesweeper grid, shared by the commands creating a grid
+fn create_grid_options(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
         .create_option(|option| {
             option
                 .name("width")
@@ -1,9 +1,23 @@
+use crate::discord_command::buttons::quit::remove_grid;
+use crate::game::{Game, Player};
+use crate::Handler;
 use eyre::Error;
+use serenity::builder::{CreateActionRow, CreateComponents};
+use serenity::client::Context;
+use serenity::model::prelude::component::ButtonStyle;
 use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::{InteractionResponseType, ReactionType};
+use std::ops::DerefMut;
 use tracing::instrument;
 
+pub mod autoplay;
+pub mod human;
+pub mod output;
+pub mod pan;
 pub mod play;
 pub mod quit;
+pub mod step;
+pub mod transcript;
 
 #[instrument]
 fn parse_first_line_game_id(command: &MessageComponentInteraction) -> eyre::Result<usize> {
@@ -21,3 +35,160 @@ fn parse_first_line_game_id(command: &MessageComponentInteraction) -> eyre::Resu
     }
     Ok(first_line[14..].parse()?)
 }
+
+fn check_owner(game: &Game, command: &MessageComponentInteraction) -> eyre::Result<()> {
+    if game.owner != command.user.id {
+        return Err(Error::msg(format!(
+            "Only <@{}> can play this game",
+            game.owner
+        )));
+    }
+    Ok(())
+}
+
+pub fn create_pan_row(row: &mut CreateActionRow) -> &mut CreateActionRow {
+    for (custom_id, emoji) in [
+        ("pan_left_button_id", "⬅️"),
+        ("pan_up_button_id", "⬆️"),
+        ("pan_down_button_id", "⬇️"),
+        ("pan_right_button_id", "➡️"),
+    ] {
+        row.create_button(|button| {
+            button
+                .custom_id(custom_id)
+                .emoji(ReactionType::Unicode(emoji.to_string()))
+                .style(ButtonStyle::Secondary)
+        });
+    }
+    row
+}
+
+/// Number of moves that can be played at once with the step menu
+const STEP_CHOICES: [usize; 4] = [1, 5, 10, 25];
+
+pub fn create_quit_button(row: &mut CreateActionRow) -> &mut CreateActionRow {
+    row.create_button(|button| {
+        button
+            .custom_id("quit_button_id")
+            .label("Quit")
+            .emoji(ReactionType::Unicode("🛑".to_string()))
+            .style(ButtonStyle::Danger)
+    })
+}
+
+pub fn create_game_components<'a>(
+    components: &'a mut CreateComponents,
+    game: &Game,
+) -> &'a mut CreateComponents {
+    match game.player {
+        Player::Bot(_) => {
+            components.create_action_row(|row| {
+                row.create_button(|button| {
+                    button
+                        .custom_id("play_button_id")
+                        .label("Play")
+                        .emoji(ReactionType::Unicode("▶️".to_string()))
+                        .style(ButtonStyle::Success)
+                        .disabled(game.autoplay.is_some())
+                });
+                row.create_button(|button| {
+                    if game.autoplay.is_some() {
+                        button
+                            .custom_id("pause_button_id")
+                            .label("Pause")
+                            .emoji(ReactionType::Unicode("⏸️".to_string()))
+                    } else {
+                        button
+                            .custom_id("autoplay_button_id")
+                            .label("Autoplay")
+                            .emoji(ReactionType::Unicode("⏩".to_string()))
+                    }
+                    .style(ButtonStyle::Primary)
+                });
+                row.create_button(|button| {
+                    button
+                        .custom_id("bot_output_button_id")
+                        .label("Show bot output")
+                        .emoji(ReactionType::Unicode("📜".to_string()))
+                        .style(ButtonStyle::Secondary)
+                        .disabled(game.last_run.is_none())
+                });
+                create_quit_button(row)
+            });
+            components.create_action_row(|row| {
+                row.create_select_menu(|menu| {
+                    menu.custom_id("step_select_id")
+                        .placeholder("Play several moves")
+                        .disabled(game.autoplay.is_some())
+                        .options(|options| {
+                            for moves in STEP_CHOICES {
+                                options.create_option(|option| {
+                                    match moves {
+                                        1 => option.label("1 move"),
+                                        _ => option.label(format!("{} moves", moves)),
+                                    }
+                                    .value(moves)
+                                });
+                            }
+                            options
+                        })
+                })
+            });
+            if game.needs_viewport() {
+                components.create_action_row(create_pan_row);
+            }
+            components
+        }
+        Player::Human => human::create_human_components(components, game),
+    }
+}
+
+/// Components of the message of a bot game that is over, to download its transcript
+pub fn create_ended_components<'a>(
+    components: &'a mut CreateComponents,
+    transcript: &str,
+) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("transcript_{}", transcript))
+                .label("Download transcript")
+                .emoji(ReactionType::Unicode("📥".to_string()))
+                .style(ButtonStyle::Secondary)
+        })
+    })
+}
+
+/// Remove a finished game and replace its message by the final content without buttons
+pub async fn end_game(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+    game_id: usize,
+    content: String,
+) -> eyre::Result<()> {
+    let mut grids = handler.grids.write().await;
+    remove_grid(&handler.runner, grids.deref_mut(), game_id).await?;
+    drop(grids);
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| message.content(content).components(|c| c))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Show an error to the user who pressed a button whose interaction was already answered with
+/// a deferred response, the error of a handler only reaches users who got no response yet
+pub async fn send_deferred_error(
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+    error: Error,
+) -> eyre::Result<()> {
+    command
+        .create_followup_message(&ctx.http, |message| message.content(error).ephemeral(true))
+        .await?;
+    Ok(())
+}
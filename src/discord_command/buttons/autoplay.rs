@@ -0,0 +1,180 @@
+use crate::discord_command::buttons::play::play_turn;
+use crate::discord_command::buttons::quit::remove_grid;
+use crate::discord_command::buttons::{
+    create_ended_components, create_game_components, parse_first_line_game_id,
+};
+use crate::game::{Game, Player};
+use crate::runner::Runner;
+use crate::Handler;
+use eyre::Error;
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, MessageId};
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionResponseType;
+use std::collections::HashMap;
+use std::ops::DerefMut;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::log::{debug, error};
+
+/// Minimum time between two edits of the game message, to stay under Discord rate limits
+const AUTOPLAY_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Start a background task playing the bot until the game ends or is paused
+pub async fn autoplay_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!("User {} pressed Autoplay", command.user.name);
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    if let Player::Human = game.player {
+        return Err(Error::msg("This game is played with the cell buttons"));
+    }
+    if game.autoplay.is_some() {
+        return Err(Error::msg("Autoplay is already running"));
+    }
+    game.autoplay = Some(tokio::spawn(autoplay(
+        handler.grids.clone(),
+        handler.runner.clone(),
+        ctx.http.clone(),
+        command.channel_id,
+        command.message.id,
+        game_id,
+    )));
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.components(|c| create_game_components(c, &game))
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Stop the autoplay of a game
+pub async fn pause_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!("User {} pressed Pause", command.user.name);
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    if let Some(autoplay) = game.autoplay.take() {
+        autoplay.abort();
+    }
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .content(game.to_discord_message(game_id))
+                        .components(|c| create_game_components(c, &game))
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Play turns until the game is over, editing the game message at most once every
+/// `AUTOPLAY_EDIT_INTERVAL`
+async fn autoplay(
+    grids: Arc<RwLock<HashMap<usize, Arc<Mutex<Game>>>>>,
+    runner: Runner,
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    game_id: usize,
+) {
+    let mut last_edit = Instant::now();
+    loop {
+        // The map of the games stays unlocked during the turn, for the other games and Quit
+        let Some(game_lock) = grids.read().await.get(&game_id).cloned() else {
+            return;
+        };
+        let mut game = game_lock.lock().await;
+        let result = play_turn(&runner, game_id, &mut game).await;
+
+        let (edit, finished) = match result {
+            Ok(turn) if turn.game_over => {
+                // The task is finishing by itself, it must not be aborted by `remove_grid`
+                game.autoplay = None;
+                let content = game.to_discord_ended_message();
+                let transcript = game.transcript.clone();
+                drop(game);
+                let mut grids_write = grids.write().await;
+                if let Err(why) = remove_grid(&runner, grids_write.deref_mut(), game_id).await {
+                    error!("Error autoplay: {}", why);
+                }
+                drop(grids_write);
+                let edit = channel_id
+                    .edit_message(&http, message_id, |message| {
+                        message
+                            .content(content)
+                            .components(|c| create_ended_components(c, &transcript))
+                    })
+                    .await;
+                (edit, true)
+            }
+            Ok(_) if last_edit.elapsed() < AUTOPLAY_EDIT_INTERVAL => continue,
+            Ok(_) => {
+                last_edit = Instant::now();
+                let edit = channel_id
+                    .edit_message(&http, message_id, |message| {
+                        message
+                            .content(game.to_discord_message(game_id))
+                            .components(|c| create_game_components(c, &game))
+                    })
+                    .await;
+                (edit, false)
+            }
+            // Quit removes the game and its buttons once the turn stops
+            Err(_) if runner.is_killed(game_id) => return,
+            Err(error) => {
+                game.autoplay = None;
+                let edit = channel_id
+                    .edit_message(&http, message_id, |message| {
+                        message
+                            .content(format!(
+                                "{}\n⚠️ Autoplay stopped: {}",
+                                game.to_discord_message(game_id),
+                                error
+                            ))
+                            .components(|c| create_game_components(c, &game))
+                    })
+                    .await;
+                (edit, true)
+            }
+        };
+
+        if let Err(why) = edit {
+            error!("Error autoplay: {}", why);
+        }
+        if finished {
+            return;
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::discord_command::buttons::{
+    check_owner, create_game_components, create_quit_button, end_game, parse_first_line_game_id,
+};
+use crate::game::{Game, CELL_PAGE_HEIGHT, CELL_PAGE_WIDTH};
+use crate::Handler;
+use eyre::Error;
+use minesweeper::MinesweeperCellType;
+use serenity::builder::CreateComponents;
+use serenity::client::Context;
+use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::{InteractionResponseType, ReactionType};
+use tracing::instrument;
+use tracing::log::debug;
+
+/// Reveal or flag the cell of the button pressed
+pub async fn cell_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!(
+        "User {} pressed {}",
+        command.user.name, command.data.custom_id
+    );
+
+    let game_id = parse_first_line_game_id(command)?;
+    let (xpos, ypos) = parse_cell_custom_id(command.data.custom_id.as_str())?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    check_owner(&game, command)?;
+
+    if game.flag_mode {
+        game.grid
+            .toggle_flag(xpos, ypos)
+            .ok_or(Error::msg("This cell is already revealed"))?;
+    } else {
+        if game
+            .grid
+            .get_cell(xpos, ypos)
+            .is_some_and(|cell| cell.is_flagged())
+        {
+            return Err(Error::msg("Remove the flag before revealing this cell"));
+        }
+        if game.grid.discover(xpos, ypos).is_none() || game.grid.is_won() {
+            let content = game.to_discord_ended_message();
+            drop(game);
+            drop(grids);
+            return end_game(handler, ctx, command, game_id, content).await;
+        }
+    }
+
+    update_game_message(ctx, command, game_id, &game).await
+}
+
+/// Switch between revealing and flagging cells
+pub async fn mode_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!("User {} pressed Mode", command.user.name);
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    check_owner(&game, command)?;
+    game.flag_mode = !game.flag_mode;
+
+    update_game_message(ctx, command, game_id, &game).await
+}
+
+/// Show the previous or next page of cell buttons
+pub async fn page_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!(
+        "User {} pressed {}",
+        command.user.name, command.data.custom_id
+    );
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    check_owner(&game, command)?;
+    let page_count = game.page_count();
+    game.page = match command.data.custom_id.as_str() {
+        "page_previous_button_id" => (game.page + page_count - 1) % page_count,
+        _ => (game.page + 1) % page_count,
+    };
+
+    update_game_message(ctx, command, game_id, &game).await
+}
+
+pub fn create_human_components<'a>(
+    components: &'a mut CreateComponents,
+    game: &Game,
+) -> &'a mut CreateComponents {
+    let (xstart, ystart) = game.page_start();
+    for xpos in xstart..(xstart + CELL_PAGE_WIDTH).min(game.grid.width()) {
+        components.create_action_row(|row| {
+            for ypos in ystart..(ystart + CELL_PAGE_HEIGHT).min(game.grid.height()) {
+                let cell = game
+                    .grid
+                    .get_cell(xpos, ypos)
+                    .map_or(MinesweeperCellType::Hidden, |cell| cell.hide_bomb());
+                row.create_button(|button| {
+                    button
+                        .custom_id(format!("cell_{}_{}", xpos, ypos))
+                        .emoji(ReactionType::Unicode(cell.to_discord_emoji().to_string()))
+                        .style(match cell {
+                            MinesweeperCellType::Flagged => ButtonStyle::Danger,
+                            MinesweeperCellType::Found(_) => ButtonStyle::Secondary,
+                            _ => ButtonStyle::Primary,
+                        })
+                        .disabled(matches!(cell, MinesweeperCellType::Found(_)))
+                });
+            }
+            row
+        });
+    }
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            if game.flag_mode {
+                button
+                    .label("Flag")
+                    .emoji(ReactionType::Unicode("🚩".to_string()))
+            } else {
+                button
+                    .label("Reveal")
+                    .emoji(ReactionType::Unicode("🔍".to_string()))
+            }
+            .custom_id("mode_button_id")
+            .style(ButtonStyle::Success)
+        });
+        for (custom_id, emoji) in [
+            ("page_previous_button_id", "◀️"),
+            ("page_next_button_id", "▶️"),
+        ] {
+            row.create_button(|button| {
+                button
+                    .custom_id(custom_id)
+                    .emoji(ReactionType::Unicode(emoji.to_string()))
+                    .style(ButtonStyle::Secondary)
+                    .disabled(game.page_count() <= 1)
+            });
+        }
+        create_quit_button(row)
+    })
+}
+
+async fn update_game_message(
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+    game_id: usize,
+    game: &Game,
+) -> eyre::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .content(game.to_discord_message(game_id))
+                        .components(|c| create_game_components(c, game))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+#[instrument]
+fn parse_cell_custom_id(custom_id: &str) -> eyre::Result<(usize, usize)> {
+    let (xpos, ypos) = custom_id
+        .strip_prefix("cell_")
+        .and_then(|position| position.split_once('_'))
+        .ok_or(Error::msg(format!("Bad cell button: {}", custom_id)))?;
+    Ok((xpos.parse()?, ypos.parse()?))
+}
@@ -0,0 +1,80 @@
+use crate::discord_command::buttons::{check_owner, parse_first_line_game_id};
+use crate::runner::BotRun;
+use crate::Handler;
+use eyre::Error;
+use serenity::client::Context;
+use serenity::model::application::interaction::MessageFlags;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionResponseType;
+use tracing::log::debug;
+
+/// Maximum number of characters of stdout or stderr shown, to stay under the Discord limit
+const MAX_OUTPUT_LENGTH: usize = 800;
+
+/// Show the owner the output of the bot for the last move in an ephemeral message
+pub async fn bot_output_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!("User {} pressed Show bot output", command.user.name);
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let game = game_lock.lock().await;
+    check_owner(&game, command)?;
+    let bot_run = game
+        .last_run
+        .as_ref()
+        .ok_or(Error::msg("The bot has not played yet"))?;
+    let content = format_bot_run(bot_run);
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content).flags(MessageFlags::EPHEMERAL)
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn format_bot_run(bot_run: &BotRun) -> String {
+    let fuel = match bot_run.stats.fuel {
+        Some(fuel) => format!(" and {} fuel", fuel),
+        None => String::new(),
+    };
+    format!(
+        "Bot ended with {} after {} ms of wall time{}\n**stdout**\n```\n{}\n```\n**stderr**\n```\n{}\n```",
+        bot_run.exit,
+        bot_run.stats.wall_time.as_millis(),
+        fuel,
+        truncate_output(&bot_run.stdout),
+        truncate_output(&bot_run.stderr)
+    )
+}
+
+/// Keep the end of an output, where errors usually are
+fn truncate_output(output: &str) -> String {
+    let output = output.replace("```", "'''");
+    let length = output.chars().count();
+    if length <= MAX_OUTPUT_LENGTH {
+        return output;
+    }
+    let end = output
+        .chars()
+        .skip(length - MAX_OUTPUT_LENGTH)
+        .collect::<String>();
+    format!(
+        "[{} characters truncated]\n{}",
+        length - MAX_OUTPUT_LENGTH,
+        end
+    )
+}
@@ -0,0 +1,72 @@
+use crate::discord_command::buttons::{create_pan_row, parse_first_line_game_id};
+use crate::Handler;
+use eyre::Error;
+use serenity::client::Context;
+use serenity::model::application::interaction::MessageFlags;
+use serenity::model::channel;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionResponseType;
+use tracing::log::debug;
+
+/// Show the grid around the window of the user, in an ephemeral message so that
+/// each viewer can look at a different part of the grid
+pub async fn pan_button(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!(
+        "User {} pressed {}",
+        command.user.name, command.data.custom_id
+    );
+
+    let game_id = parse_first_line_game_id(command)?;
+    let (xdirection, ydirection) = match command.data.custom_id.as_str() {
+        "pan_up_button_id" => (-1, 0),
+        "pan_down_button_id" => (1, 0),
+        "pan_left_button_id" => (0, -1),
+        "pan_right_button_id" => (0, 1),
+        _ => return Err(Error::msg("Unknown direction")),
+    };
+
+    let grids = handler.grids.read().await;
+    let game_lock = grids
+        .get(&game_id)
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let mut game = game_lock.lock().await;
+    let viewport = game.pan(command.user.id, xdirection, ydirection);
+    let content = format!(
+        "# Minesweeper {}\n{}",
+        game_id,
+        game.to_discord_string(viewport)
+    );
+
+    let is_ephemeral = command
+        .message
+        .flags
+        .is_some_and(|flags| flags.contains(channel::MessageFlags::EPHEMERAL));
+    if is_ephemeral {
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|message| message.content(content))
+            })
+            .await?;
+    } else {
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message
+                            .content(content)
+                            .flags(MessageFlags::EPHEMERAL)
+                            .components(|c| c.create_action_row(create_pan_row))
+                    })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
@@ -1,19 +1,22 @@
-use crate::discord_command::buttons::parse_first_line_game_id;
+use crate::discord_command::buttons::quit::remove_grid;
+use crate::discord_command::buttons::{
+    create_ended_components, create_game_components, parse_first_line_game_id, send_deferred_error,
+};
+use crate::game::{Game, Penalty, Player};
+use crate::protocol::{encode_input, game_state, parse_output, Action, BotOutput, Protocol};
+use crate::runner::component::is_component;
+use crate::runner::determinism::{penalty_rng, turn_seed};
+use crate::runner::transcript::{record_turn, TranscriptHeader, TurnRecord};
+use crate::runner::{BotExit, Runner};
 use crate::Handler;
 use eyre::Error;
-use minesweeper::MinesweeperGrid;
+use minesweeper::{MinesweeperCellType, MinesweeperGrid};
 use serenity::client::Context;
 use serenity::model::prelude::message_component::MessageComponentInteraction;
 use serenity::model::prelude::InteractionResponseType;
-use std::io;
-use std::io::{ErrorKind, Write};
 use std::ops::DerefMut;
-use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::time::Duration;
-use tracing::instrument;
-use tracing::log::debug;
-use crate::discord_command::buttons::quit::remove_grid;
+use tokio::sync::MutexGuard;
+use tracing::log::{debug, warn};
 
 pub async fn play_button(
     handler: &Handler,
@@ -24,113 +27,401 @@ pub async fn play_button(
 
     let game_id = parse_first_line_game_id(command)?;
 
-    let grids = handler.grids.read().await;
-    let game_lock = grids
+    // The map of the games is not kept locked during the move, for the other games and Quit
+    let game_lock = handler
+        .grids
+        .read()
+        .await
         .get(&game_id)
+        .cloned()
         .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
-    let mut game_data = game_lock.lock().await;
-    let (grid, file_path) = game_data.deref_mut();
-    let (xpos, ypos) = run_file(grid, file_path.as_path()).await?;
-    let res = grid.discover(xpos, ypos);
-    if res.is_some() {
+    let game = game_lock.lock().await;
+    if game.autoplay.is_some() {
+        return Err(Error::msg("Pause the autoplay before playing"));
+    }
+    // With a big clock, a move can take longer than the time Discord waits for a response
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+    if let Err(error) = play_deferred(handler, ctx, command, game_id, game).await {
+        send_deferred_error(ctx, command, error).await?;
+    }
+    Ok(())
+}
+
+/// Play the move of a Play button once its interaction is deferred
+async fn play_deferred(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+    game_id: usize,
+    mut game: MutexGuard<'_, Game>,
+) -> eyre::Result<()> {
+    let result = play_turn(&handler.runner, game_id, &mut game).await;
+    if !result.as_ref().is_ok_and(|turn| turn.game_over) {
+        // The error is shown in the game message so the bot output stays reachable
+        let mut content = game.to_discord_message(game_id);
+        if let Err(error) = result {
+            content.push_str(&format!("\n⚠️ {}", error));
+        }
         command
-            .create_interaction_response(&ctx.http, |response| {
+            .edit_original_interaction_response(&ctx.http, |response| {
                 response
-                    .kind(InteractionResponseType::UpdateMessage)
-                    .interaction_response_data(|message| {
-                        message.content(format!(
-                            "# Minesweeper {}\n{}",
-                            game_id,
-                            grid.to_discord_string()
-                        ))
-                    })
+                    .content(content)
+                    .components(|c| create_game_components(c, &game))
             })
-            .await?
+            .await?;
     } else {
-        let grid_string = grid.to_discord_string();
-        drop(game_data); // Why do I need to drop it manually ?
-        drop(grids);
+        let content = game.to_discord_ended_message();
+        let transcript = game.transcript.clone();
+        drop(game); // Why do I need to drop it manually ?
         let mut grids = handler.grids.write().await;
-        remove_grid(grids.deref_mut(), game_id).await?;
+        remove_grid(&handler.runner, grids.deref_mut(), game_id).await?;
+        drop(grids);
         command
-            .create_interaction_response(&ctx.http, |response| {
+            .edit_original_interaction_response(&ctx.http, |response| {
                 response
-                    .kind(InteractionResponseType::UpdateMessage)
-                    .interaction_response_data(|message| {
-                        message
-                            .content(format!("# Minesweeper ENDED\n{}", grid_string))
-                            .components(|c| c)
-                    })
+                    .content(content)
+                    .components(|c| create_ended_components(c, &transcript))
             })
-            .await?
+            .await?;
     }
     Ok(())
 }
 
-#[instrument]
-async fn run_wasm(grid_console_string: String, file_path: PathBuf) -> eyre::Result<(usize, usize)> {
-    let file_extension = file_path
-        .extension()
-        .ok_or(Error::msg("Internal error with file extension"))?;
-    let mut child = if file_extension == "wasm" {
-        Command::new("wasmtime")
-            .arg("run")
-            .arg(file_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?
-    } else {
-        let content = tokio::fs::read_to_string(file_path).await?;
-
-        Command::new("wasmtime")
-            .arg("run")
-            .arg("--mapdir=./lib/python3.10::./python/lib/python3.10")
-            .arg("--")
-            .arg("./python/python3.10.wasm")
-            .arg("-c")
-            .arg(content)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?
-    };
+pub struct Turn {
+    /// Number of cells revealed by the move
+    pub revealed: usize,
+    pub game_over: bool,
+}
 
-    child
-        .stdin
-        .as_mut()
-        .ok_or(io::Error::new(ErrorKind::NotFound, "No stdin"))?
-        .write_all(grid_console_string.as_bytes())?;
+/// Ask the bot of a game for its actions and play them, a bot failing to play gets a strike.
+/// Fails without a strike when the game was quit during the move
+pub async fn play_turn(runner: &Runner, game_id: usize, game: &mut Game) -> eyre::Result<Turn> {
+    if game.protocol.is_none() {
+        if let Player::Bot(file_path) = &game.player {
+            if is_component(file_path).await? {
+                game.protocol = Some(Protocol::Component);
+            }
+        }
+    }
+    let mut output = run_bot(
+        runner,
+        game_id,
+        game,
+        game.protocol.unwrap_or(Protocol::Console),
+    )
+    .await?;
+    if game.protocol.is_none() {
+        if let Ok(first_output) = &output {
+            game.protocol = Some(first_output.declared.unwrap_or(Protocol::Console));
+            // The bot declared the JSON protocol after reading the console board, replay the turn
+            if first_output.declared.is_some() && first_output.actions.is_empty() {
+                output = run_bot(runner, game_id, game, Protocol::Json).await?;
+            }
+        }
+    }
 
-    let output = child.wait_with_output()?;
-    let decoded_output = String::from_utf8_lossy(&output.stdout);
-    let output_parts = decoded_output.split(',').collect::<Vec<&str>>();
+    // Running out of time loses the game, whatever the bot answered
+    if game.clock.is_out() {
+        return Ok(Turn {
+            revealed: 0,
+            game_over: true,
+        });
+    }
 
-    let tmp_xpos_str = output_parts
-        .first()
-        .ok_or(Error::msg(format!("Cannot parse xpos: {}", decoded_output)))?
-        .trim();
+    let result = output.and_then(|output| play_actions(game, output.actions));
+    game.moves += 1;
+    match result {
+        Ok(turn) => {
+            game.last_strike = None;
+            Ok(turn)
+        }
+        Err(fault) => Ok(strike(game, fault)),
+    }
+}
 
-    if tmp_xpos_str.is_empty() {
-        return Err(Error::msg(format!("Empty xpos: {}", decoded_output)));
+/// Play the actions of a bot in order, until one of them ends the game
+fn play_actions(game: &mut Game, actions: Vec<Action>) -> eyre::Result<Turn> {
+    if actions.is_empty() {
+        return Err(Error::msg("The bot did not play any action"));
+    }
+    let mut revealed = 0;
+    for action in actions {
+        game.last_move = Some(action.position());
+        match play_action(&mut game.grid, action)? {
+            Some(action_revealed) => revealed += action_revealed,
+            None => {
+                return Ok(Turn {
+                    revealed,
+                    game_over: true,
+                })
+            }
+        }
+        if game.grid.is_won() {
+            return Ok(Turn {
+                revealed,
+                game_over: true,
+            });
+        }
     }
+    Ok(Turn {
+        revealed,
+        game_over: false,
+    })
+}
 
-    let xpos = tmp_xpos_str[1..].parse()?;
+/// Count a failed turn against the bot and apply the penalty of the game, the bot forfeits
+/// after the last strike allowed
+fn strike(game: &mut Game, fault: Error) -> Turn {
+    debug!("Strike {} for the bot: {}", game.strikes + 1, fault);
+    game.strikes += 1;
+    game.last_strike = Some(fault.to_string());
+    if game.is_forfeited() {
+        return Turn {
+            revealed: 0,
+            game_over: true,
+        };
+    }
+    let mut revealed = 0;
+    if game.rules.penalty == Penalty::SafeReveal {
+        let mut rng = penalty_rng(turn_seed(game.seed, game.moves));
+        if let Some((xpos, ypos)) = game.grid.find_safe_opening(&mut rng) {
+            game.last_move = Some((xpos, ypos));
+            revealed = game.grid.discover(xpos, ypos).unwrap_or(0);
+            game.penalty_revealed += revealed;
+        }
+    }
+    Turn {
+        revealed,
+        game_over: game.grid.is_won(),
+    }
+}
 
-    let tmp_ypos_str = output_parts
-        .get(1)
-        .ok_or(Error::msg(format!("Cannot parse ypos: {}", decoded_output)))?
-        .trim();
+/// Run the bot of a game once, with its input encoded with `protocol`, the inner error is a
+/// fault of the bot and the outer one a failure to run it
+async fn run_bot(
+    runner: &Runner,
+    game_id: usize,
+    game: &mut Game,
+    protocol: Protocol,
+) -> eyre::Result<eyre::Result<BotOutput>> {
+    let Player::Bot(file_path) = &game.player else {
+        return Err(Error::msg("This game is played with the cell buttons"));
+    };
+    let scratch = game.scratch.as_deref();
+    let seed = turn_seed(game.seed, game.moves);
+    let time_left = runner.move_time(game.session_mode, game.clock.remaining);
+    let mut input = encode_input(game, protocol, time_left);
+    let (bot_run, output) = if protocol == Protocol::Component {
+        let (bot_run, action) = runner
+            .run_component(
+                game_id,
+                file_path.as_path(),
+                seed,
+                time_left,
+                game_state(game, time_left),
+            )
+            .await?;
+        let output = BotOutput {
+            declared: None,
+            actions: action.into_iter().collect(),
+        };
+        (bot_run, Ok(output))
+    } else {
+        let bot_run = if game.session_mode {
+            let session = match game.session.take() {
+                Some(session) if !session.is_closed() => session,
+                _ => {
+                    runner
+                        .start_session(game_id, file_path.as_path(), scratch, seed)
+                        .await?
+                }
+            };
+            // A session reads the whole console board on one line, with each line followed by
+            // '/', so a board of one line is told apart from the board of a run per move. The
+            // JSON board is already one line
+            if protocol == Protocol::Console {
+                input = format!("{}/", input.replace('\n', "/"));
+            }
+            let timeout = time_left.unwrap_or(game.clock.remaining);
+            let bot_run = session.play(input.clone(), timeout).await;
+            game.session = Some(session);
+            bot_run?
+        } else {
+            runner
+                .run(
+                    game_id,
+                    file_path.as_path(),
+                    scratch,
+                    seed,
+                    time_left,
+                    input.clone(),
+                )
+                .await?
+        };
+        let output = parse_output(
+            &bot_run.stdout,
+            protocol,
+            game.grid.width(),
+            game.grid.height(),
+        );
+        (bot_run, output)
+    };
+    // A game quit during its move stops there, the killed run is not the fault of the bot
+    if runner.is_killed(game_id) {
+        return Err(Error::msg(format!("Game {} was quit", game_id)));
+    }
+    // The run was stopped at the deadline of the move, the time it took past it is not charged
+    if let Some(time_left) = time_left {
+        match bot_run.exit {
+            BotExit::OutOfTime(_) => game.clock.spend(time_left),
+            _ => game.clock.spend(bot_run.stats.wall_time.min(time_left)),
+        }
+    }
+    let output = if bot_run.exit.is_success() {
+        output
+    } else {
+        Err(Error::msg(format!(
+            "Bot failed with {}, see Show bot output",
+            bot_run.exit
+        )))
+    };
+    let record = TurnRecord::new(game.moves, protocol, seed, input, &bot_run, &output);
+    let header = TranscriptHeader {
+        game: game_id,
+        owner: game.owner.0,
+    };
+    if let Err(error) = record_turn(&game.transcript, &header, &record).await {
+        warn!("Cannot record the turn of game {}: {}", game_id, error);
+    }
+    game.last_run = Some(bot_run);
+    Ok(output)
+}
 
-    if tmp_ypos_str.is_empty() {
-        return Err(Error::msg(format!("Empty ypos: {}", decoded_output)));
+/// Play one action and return the number of cells revealed, or `None` if the game is lost
+fn play_action(grid: &mut MinesweeperGrid, action: Action) -> eyre::Result<Option<usize>> {
+    let (xpos, ypos) = action.position();
+    let cell = *grid.get_cell(xpos, ypos).ok_or(Error::msg(format!(
+        "Position ({},{}) is outside of the grid",
+        xpos, ypos
+    )))?;
+    match action {
+        Action::Reveal { .. } => {
+            if let MinesweeperCellType::Found(_) = cell {
+                return Err(Error::msg(format!(
+                    "Cannot reveal the revealed cell ({},{})",
+                    xpos, ypos
+                )));
+            }
+            if cell.is_flagged() {
+                return Err(Error::msg(format!(
+                    "Cannot reveal the flagged cell ({},{})",
+                    xpos, ypos
+                )));
+            }
+            Ok(grid.discover(xpos, ypos))
+        }
+        Action::Flag { .. } | Action::Unflag { .. } => {
+            if cell.is_flagged() == matches!(action, Action::Flag { .. }) {
+                return Ok(Some(0));
+            }
+            grid.toggle_flag(xpos, ypos).ok_or(Error::msg(format!(
+                "Cannot flag the revealed cell ({},{})",
+                xpos, ypos
+            )))?;
+            Ok(Some(0))
+        }
+        Action::Chord { .. } => Ok(grid.chord(xpos, ypos)),
     }
+}
 
-    let ypos = tmp_ypos_str[..tmp_ypos_str.len() - 1].parse()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Renderer;
+    use crate::runner::languages::Languages;
+    use crate::runner::RunnerLimits;
+    use serenity::model::id::UserId;
+    use std::path::Path;
 
-    Ok((xpos, ypos))
-}
+    /// Session bot answering each line ending with `}` (a JSON board) by flagging (0,0), and
+    /// any other line (a console board) by declaring the JSON protocol
+    const JSON_BOT: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_read" (func $read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write" (func $write (param i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 200) "{\"protocol\":1}\n")
+        (data (i32.const 300) "{\"action\":\"flag\",\"x\":0,\"y\":0}\n")
+        (func $answer (param $text i32) (param $length i32)
+            (i32.store (i32.const 16) (local.get $text))
+            (i32.store (i32.const 20) (local.get $length))
+            (drop (call $write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24))))
+        (func (export "_start") (local $byte i32) (local $previous i32)
+            (i32.store (i32.const 0) (i32.const 1024))
+            (i32.store (i32.const 4) (i32.const 1))
+            (loop $lines
+                (if (call $read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8))
+                    (then (return)))
+                (if (i32.eqz (i32.load (i32.const 8)))
+                    (then (return)))
+                (local.set $byte (i32.load8_u (i32.const 1024)))
+                (if (i32.eq (local.get $byte) (i32.const 10))
+                    (then
+                        (if (i32.eq (local.get $previous) (i32.const 125))
+                            (then (call $answer (i32.const 300) (i32.const 30)))
+                            (else (call $answer (i32.const 200) (i32.const 15)))))
+                    (else (local.set $previous (local.get $byte))))
+                (br $lines))))"#;
+
+    /// Bot game in a directory of its own, named `name` like the test
+    fn bot_game(name: &str) -> (Runner, Game) {
+        let directory = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file_path = directory.join("bot.wasm");
+        std::fs::write(&file_path, wat::parse_str(JSON_BOT).unwrap()).unwrap();
+        let languages = Languages::load(Path::new("runners.toml")).unwrap();
+        let runner = Runner::new(RunnerLimits::default(), languages).unwrap();
+        let grid = MinesweeperGrid::new(3, 3, 0.0);
+        let game = Game::new(grid, Player::Bot(file_path), UserId(1), Renderer::Emoji);
+        (runner, game)
+    }
 
-#[instrument]
-async fn run_file(grid: &MinesweeperGrid, file_path: &Path) -> eyre::Result<(usize, usize)> {
-    let handle = tokio::task::spawn(run_wasm(grid.to_console_string(), file_path.to_path_buf()));
-    tokio::time::timeout(Duration::from_secs(2), handle).await??
+    fn remove_bot(runner: &Runner, game: &mut Game) {
+        drop(game.session.take());
+        if let Player::Bot(file_path) = &game.player {
+            let directory = file_path.parent().unwrap();
+            runner.forget(directory).unwrap();
+            std::fs::remove_dir_all(directory).unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn json_bot_plays_in_a_session() {
+        let (runner, mut game) = bot_game("session-json");
+        game.session_mode = true;
+        let turn = play_turn(&runner, 0, &mut game).await;
+        remove_bot(&runner, &mut game);
+        assert!(turn.is_ok());
+        assert_eq!(game.protocol, Some(Protocol::Json));
+        assert_eq!(game.strikes, 0, "{:?}", game.last_strike);
+        assert_eq!(
+            game.grid.get_cell(0, 0),
+            Some(&MinesweeperCellType::Flagged)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quit_game_gets_no_strike() {
+        let (runner, mut game) = bot_game("quit-game");
+        runner.kill(0);
+        let turn = play_turn(&runner, 0, &mut game).await;
+        remove_bot(&runner, &mut game);
+        assert!(turn.is_err());
+        assert_eq!(game.strikes, 0);
+        assert_eq!(game.moves, 0);
+    }
 }
@@ -1,14 +1,15 @@
-use std::collections::HashMap;
 use crate::discord_command::buttons::parse_first_line_game_id;
+use crate::game::{Game, Player};
+use crate::runner::Runner;
 use crate::Handler;
 use serenity::client::Context;
 use serenity::model::prelude::message_component::MessageComponentInteraction;
 use serenity::model::prelude::InteractionResponseType;
-use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::ops::DerefMut;
+use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::log::debug;
-use minesweeper::MinesweeperGrid;
 
 pub async fn quit_button(
     handler: &Handler,
@@ -19,8 +20,16 @@ pub async fn quit_button(
 
     let game_id = parse_first_line_game_id(command)?;
 
+    // Kill the bot first, a running move or batch of moves keeps the game locked. It is waited
+    // for before locking the map of the games, so the other games are not blocked meanwhile
+    handler.runner.kill(game_id);
+    let game_lock = handler.grids.read().await.get(&game_id).cloned();
+    if let Some(game_lock) = game_lock {
+        drop(game_lock.lock().await);
+    }
     let mut grids = handler.grids.write().await;
-    remove_grid(grids.deref_mut(), game_id).await?;
+    remove_grid(&handler.runner, grids.deref_mut(), game_id).await?;
+    drop(grids);
 
     command
         .create_interaction_response(&ctx.http, |response| {
@@ -33,11 +42,36 @@ pub async fn quit_button(
     Ok(())
 }
 
-pub async fn remove_grid(grids: &mut HashMap<usize, Mutex<(MinesweeperGrid, PathBuf)>>, game_id: usize) -> eyre::Result<()> {
+/// Remove a game with its files, after killing what still runs for it like the snapshot of
+/// its runtime
+pub async fn remove_grid(
+    runner: &Runner,
+    grids: &mut HashMap<usize, Arc<Mutex<Game>>>,
+    game_id: usize,
+) -> eyre::Result<()> {
+    runner.kill(game_id);
+    let removed = remove_game_files(runner, grids, game_id).await;
+    runner.release(game_id);
+    removed
+}
+
+async fn remove_game_files(
+    runner: &Runner,
+    grids: &mut HashMap<usize, Arc<Mutex<Game>>>,
+    game_id: usize,
+) -> eyre::Result<()> {
     if let Some(game_lock) = grids.remove(&game_id) {
-        let game_lock = game_lock.lock().await;
-        let (_, file_path) = game_lock.deref();
-        tokio::fs::remove_file(file_path).await?;
+        let mut game = game_lock.lock().await;
+        if let Some(autoplay) = game.autoplay.take() {
+            autoplay.abort();
+        }
+        if let Player::Bot(file_path) = &game.player {
+            if let Some(directory) = file_path.parent() {
+                // Removed before being forgotten, so a file compiled meanwhile is not cached
+                tokio::fs::remove_dir_all(directory).await?;
+                runner.forget(directory)?;
+            }
+        }
     }
     Ok(())
 }
@@ -0,0 +1,124 @@
+use crate::discord_command::buttons::play::play_turn;
+use crate::discord_command::buttons::quit::remove_grid;
+use crate::discord_command::buttons::{
+    create_ended_components, create_game_components, parse_first_line_game_id, send_deferred_error,
+};
+use crate::game::Game;
+use crate::Handler;
+use eyre::Error;
+use serenity::client::Context;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionResponseType;
+use std::ops::DerefMut;
+use tokio::sync::MutexGuard;
+use tracing::log::debug;
+
+/// Play the number of moves chosen in the step menu, stopping early when the game ends
+pub async fn step_select(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    let moves: usize = command
+        .data
+        .values
+        .first()
+        .ok_or(Error::msg("No number of moves selected"))?
+        .parse()?;
+    debug!("User {} selected {} moves", command.user.name, moves);
+
+    let game_id = parse_first_line_game_id(command)?;
+
+    // The map of the games is not kept locked during the moves, for the other games and Quit
+    let game_lock = handler
+        .grids
+        .read()
+        .await
+        .get(&game_id)
+        .cloned()
+        .ok_or(Error::msg(format!("Game {} does not exists", game_id)))?;
+    let game = game_lock.lock().await;
+    if game.autoplay.is_some() {
+        return Err(Error::msg("Pause the autoplay before playing"));
+    }
+
+    // Several moves can take longer than the time Discord waits for a response
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+    if let Err(error) = step_deferred(handler, ctx, command, game_id, game, moves).await {
+        send_deferred_error(ctx, command, error).await?;
+    }
+    Ok(())
+}
+
+/// Play the moves of the step menu once its interaction is deferred
+async fn step_deferred(
+    handler: &Handler,
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+    game_id: usize,
+    mut game: MutexGuard<'_, Game>,
+    moves: usize,
+) -> eyre::Result<()> {
+    let mut moves_played = 0;
+    let mut revealed = 0;
+    let mut stop_reason = None;
+    while moves_played < moves {
+        match play_turn(&handler.runner, game_id, &mut game).await {
+            Ok(turn) => {
+                moves_played += 1;
+                revealed += turn.revealed;
+                if turn.game_over {
+                    let content = format!(
+                        "{}\n⏭️ {} moves played, {} cells revealed",
+                        game.to_discord_ended_message(),
+                        moves_played,
+                        revealed
+                    );
+                    let transcript = game.transcript.clone();
+                    drop(game);
+                    let mut grids = handler.grids.write().await;
+                    remove_grid(&handler.runner, grids.deref_mut(), game_id).await?;
+                    drop(grids);
+                    command
+                        .edit_original_interaction_response(&ctx.http, |response| {
+                            response
+                                .content(content)
+                                .components(|c| create_ended_components(c, &transcript))
+                        })
+                        .await?;
+                    return Ok(());
+                }
+            }
+            // Quit removes the game and its buttons once the moves stop
+            Err(_) if handler.runner.is_killed(game_id) => return Ok(()),
+            Err(error) => {
+                stop_reason = Some(error);
+                break;
+            }
+        }
+    }
+
+    let mut content = format!(
+        "{}\n⏭️ {} moves played, {} cells revealed",
+        game.to_discord_message(game_id),
+        moves_played,
+        revealed
+    );
+    if let Some(error) = stop_reason {
+        content.push_str(&format!("\n⚠️ Stopped early: {}", error));
+    }
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response
+                .content(content)
+                .components(|c| create_game_components(c, &game))
+        })
+        .await?;
+
+    Ok(())
+}
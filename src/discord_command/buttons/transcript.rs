@@ -0,0 +1,47 @@
+use crate::runner::transcript::read_transcript;
+use eyre::Error;
+use serenity::client::Context;
+use serenity::model::application::interaction::MessageFlags;
+use serenity::model::channel::AttachmentType;
+use serenity::model::prelude::message_component::MessageComponentInteraction;
+use serenity::model::prelude::InteractionResponseType;
+use std::borrow::Cow;
+use tracing::instrument;
+use tracing::log::debug;
+
+/// Send the owner of an ended game the transcript of its turns, one JSON object per line
+pub async fn transcript_button(
+    ctx: &Context,
+    command: &MessageComponentInteraction,
+) -> eyre::Result<()> {
+    debug!("User {} pressed Download transcript", command.user.name);
+
+    let key = parse_transcript_custom_id(command.data.custom_id.as_str())?;
+    // The game is removed once over, its owner is kept in the transcript
+    let (header, transcript) = read_transcript(key, command.user.id.0).await?;
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .content(format!("📥 Transcript of game {}", header.game))
+                        .add_file(AttachmentType::Bytes {
+                            data: Cow::Owned(transcript),
+                            filename: format!("minesweeper-{}.jsonl", header.game),
+                        })
+                        .flags(MessageFlags::EPHEMERAL)
+                })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[instrument]
+fn parse_transcript_custom_id(custom_id: &str) -> eyre::Result<&str> {
+    custom_id
+        .strip_prefix("transcript_")
+        .ok_or(Error::msg(format!("Bad transcript button: {}", custom_id)))
+}
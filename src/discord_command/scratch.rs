@@ -0,0 +1,61 @@
+use crate::runner::scratch::{scratch_path, scratch_size, wipe_scratch};
+use crate::Handler;
+use eyre::Error;
+use serenity::model::application::interaction::MessageFlags;
+use serenity::model::prelude::application_command::{
+    ApplicationCommandInteraction, CommandDataOptionValue,
+};
+use serenity::model::prelude::InteractionResponseType;
+use serenity::prelude::Context;
+use tracing::log::debug;
+
+/// Show the size of the scratch of a bot of the user, or wipe it
+pub async fn scratch_command(
+    handler: &Handler,
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> eyre::Result<()> {
+    debug!("Scratch command from user {}", command.user.name);
+
+    let mut bot_name = None;
+    let mut wipe = false;
+    for option in command.data.options.iter() {
+        match (option.name.as_str(), &option.resolved) {
+            ("bot", Some(CommandDataOptionValue::String(name))) => bot_name = Some(name.clone()),
+            ("wipe", Some(CommandDataOptionValue::Boolean(wipe_desired))) => wipe = *wipe_desired,
+            _ => (),
+        }
+    }
+    let bot_name = bot_name.ok_or(Error::msg("Missing bot name"))?;
+    let path = scratch_path(command.user.id.0, &bot_name);
+
+    let content = if wipe {
+        tokio::task::spawn_blocking(move || wipe_scratch(&path)).await??;
+        format!("🧹 The scratch of {} was wiped", bot_name)
+    } else if path.exists() {
+        let size = tokio::task::spawn_blocking(move || scratch_size(&path)).await??;
+        format!(
+            "📁 The scratch of {} uses {} of {} KiB",
+            bot_name,
+            size.div_ceil(1024),
+            handler.runner.scratch_quota() / 1024
+        )
+    } else {
+        format!(
+            "{} has no scratch, start a game with the scratch option to create it",
+            bot_name
+        )
+    };
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.content(content).flags(MessageFlags::EPHEMERAL)
+                })
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::discord_command::start::get_settings;
+use minesweeper::MinesweeperGrid;
+use serenity::model::prelude::application_command::{
+    ApplicationCommandInteraction, CommandDataOptionValue,
+};
+use serenity::model::prelude::InteractionResponseType;
+use serenity::prelude::Context;
+use tracing::instrument;
+use tracing::log::debug;
+
+/// Discord stops rendering spoiler tags after this many in one message
+const MAX_SPOILERS: usize = 99;
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+pub async fn spoiler_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> eyre::Result<()> {
+    debug!("Generate a spoiler game for user {}", command.user.name);
+
+    let settings = get_settings(command);
+    let opening = get_opening(command);
+    let (mut width, mut height) = (settings.width, settings.height);
+    let content = loop {
+        let mut grid = MinesweeperGrid::new(width, height, settings.bomb_probability);
+        if opening {
+            if let Some((xpos, ypos)) = grid.find_safe_opening(&mut rand::thread_rng()) {
+                grid.discover(xpos, ypos);
+            }
+        }
+        let content = format!(
+            "# Minesweeper {}x{} with {} 💣\n{}",
+            width,
+            height,
+            grid.bomb_count(),
+            grid.to_discord_spoiler_string()
+        );
+        let spoiler_count = content.matches("||").count() / 2;
+        if (spoiler_count <= MAX_SPOILERS && content.chars().count() <= MAX_MESSAGE_LENGTH)
+            || (width <= 2 && height <= 2)
+        {
+            break content;
+        }
+        // Shrink the biggest side until the grid fits in one message
+        if width >= height {
+            width -= 1;
+        } else {
+            height -= 1;
+        }
+    };
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| message.content(content))
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[instrument]
+fn get_opening(command: &ApplicationCommandInteraction) -> bool {
+    command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "opening")
+        .and_then(|option| match option.resolved {
+            Some(CommandDataOptionValue::Boolean(opening)) => Some(opening),
+            _ => None,
+        })
+        .unwrap_or(true)
+}
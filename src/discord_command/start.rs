@@ -1,22 +1,42 @@
+use crate::bundle::{read_bundle, Bundle, ZIP_MAGIC};
+use crate::discord_command::buttons::create_game_components;
+use crate::game::{Game, Penalty, Player, Renderer, StrikeRules};
+use crate::runner::component::is_component;
+use crate::runner::determinism::{board_rng, MAX_SEED};
+use crate::runner::languages::Language;
+use crate::runner::scratch::scratch_path;
+use crate::runner::validate::{check_wasm, compile_wat, is_wat, WASM_MAGIC};
+use crate::runner::Runner;
 use crate::Handler;
 use eyre::Error;
 use minesweeper::MinesweeperGrid;
 use serenity::model::prelude::application_command::{
     ApplicationCommandInteraction, CommandDataOptionValue,
 };
-use serenity::model::prelude::component::ButtonStyle;
-use serenity::model::prelude::{Attachment, InteractionResponseType, ReactionType};
+use serenity::model::prelude::{Attachment, InteractionResponseType};
 use serenity::prelude::Context;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 use tracing::instrument;
 use tracing::log::debug;
 
-struct MinesweeperSettings {
-    width: usize,
-    height: usize,
-    bomb_probability: f64,
+pub struct MinesweeperSettings {
+    pub width: usize,
+    pub height: usize,
+    pub bomb_probability: f64,
+    pub renderer: Renderer,
+    pub session: bool,
+    pub scratch: bool,
+    /// Seed replaying the runs of an earlier game
+    pub seed: Option<u64>,
+    pub rules: StrikeRules,
+    /// Thinking time of the bot for the whole game, scaled by the size of the grid by default
+    pub time: Option<Duration>,
+    /// Time credited to the bot after each move
+    pub increment: Option<Duration>,
 }
 
 pub async fn start_command(
@@ -26,77 +46,191 @@ pub async fn start_command(
 ) -> eyre::Result<()> {
     debug!("Start a new game from user {}", command.user.name);
 
-    let attachment = get_attachment(command).ok_or(Error::msg("No attachment given"))?;
-    if attachment.size > 100_000_000 {
-        return Err(Error::msg(format!(
-            "File too big ({}>100MB)",
-            attachment.size
-        )));
-    }
-    let content_type = attachment
-        .content_type
-        .as_ref()
-        .ok_or(Error::msg("Attachment has no content type"))?;
-    let extension = match content_type.as_str() {
-        "application/wasm" => "wasm",
-        "text/x-python" => "py",
-        "text/x-python; charset=utf-8" => "py",
-        _ => {
-            return Err(Error::msg(format!(
-                "Attachment has bad content type: {}",
-                content_type
-            )))
+    // Checking and compiling an upload can take longer than the time Discord waits for a
+    // response
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await?;
+
+    let (game_id, game) = match new_game(handler, command).await {
+        Ok(new_game) => new_game,
+        Err(error) => {
+            // Only the user sees the error, like the errors of the other commands
+            command
+                .delete_original_interaction_response(&ctx.http)
+                .await?;
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message.content(error).ephemeral(true)
+                })
+                .await?;
+            return Ok(());
         }
     };
-    let settings = get_settings(command);
-    let file_bytes = attachment.download().await?;
-    let game_id = handler.number_grid.fetch_add(1, Ordering::AcqRel); // TODO: Do better
-    let file_path = store_wasm_to_file(file_bytes.as_slice(), game_id, extension).await?;
-    let grid = MinesweeperGrid::new(settings.width, settings.height, settings.bomb_probability);
 
     command
-        .create_interaction_response(&ctx.http, |response| {
+        .edit_original_interaction_response(&ctx.http, |response| {
             response
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message
-                        .content(format!(
-                            "# Minesweeper {}\n{}",
-                            game_id,
-                            grid.to_discord_string()
-                        ))
-                        .components(|c| {
-                            c.create_action_row(|row| {
-                                row.create_button(|button| {
-                                    button
-                                        .custom_id("play_button_id")
-                                        .label("Play")
-                                        .emoji(ReactionType::Unicode("▶️".to_string()))
-                                        .style(ButtonStyle::Success)
-                                });
-                                row.create_button(|button| {
-                                    button
-                                        .custom_id("quit_button_id")
-                                        .label("Quit")
-                                        .emoji(ReactionType::Unicode("🛑".to_string()))
-                                        .style(ButtonStyle::Danger)
-                                })
-                            })
-                        })
-                })
+                .content(game.to_discord_message(game_id))
+                .components(|c| create_game_components(c, &game))
         })
         .await?;
 
     let mut grids = handler.grids.write().await;
-    grids.insert(game_id, Mutex::new((grid, file_path)));
+    grids.insert(game_id, Arc::new(Mutex::new(game)));
 
     Ok(())
 }
 
+/// Game of a /start command, with its bot stored and checked
+async fn new_game(
+    handler: &Handler,
+    command: &ApplicationCommandInteraction,
+) -> eyre::Result<(usize, Game)> {
+    let settings = get_settings(command);
+    let game_id = handler.number_grid.fetch_add(1, Ordering::AcqRel); // TODO: Do better
+
+    // Known before the upload is checked, the snapshot of its runtime uses it
+    let seed = settings
+        .seed
+        .unwrap_or_else(|| rand::random::<u64>() & MAX_SEED);
+    let attachment = get_attachment(command);
+    let player = match attachment {
+        Some(attachment) => {
+            Player::Bot(store_attachment(attachment, game_id, seed, &handler.runner).await?)
+        }
+        None => Player::Human,
+    };
+    if let Player::Bot(file_path) = &player {
+        if (settings.session || settings.scratch) && is_component(file_path).await? {
+            if let Some(directory) = file_path.parent() {
+                tokio::fs::remove_dir_all(directory).await?;
+                handler.runner.forget(directory)?;
+            }
+            return Err(Error::msg(if settings.session {
+                "Component bots are instantiated for each move, they cannot run in a session"
+            } else {
+                "The scratch quota is checked on the writes of WASI modules, component bots \
+                 cannot have a scratch"
+            }));
+        }
+    }
+    let grid = MinesweeperGrid::with_rng(
+        settings.width,
+        settings.height,
+        settings.bomb_probability,
+        &mut board_rng(seed),
+    );
+    let mut game = Game::new(grid, player, command.user.id, settings.renderer);
+    game.session_mode = settings.session;
+    game.timed = handler.runner.uses_clock(settings.session);
+    if settings.scratch {
+        game.scratch =
+            attachment.map(|attachment| scratch_path(command.user.id.0, &attachment.filename));
+    }
+    game.seed = seed;
+    game.rules = settings.rules;
+    if let Some(time) = settings.time {
+        game.clock.remaining = time;
+    }
+    if let Some(increment) = settings.increment {
+        game.clock.increment = increment;
+    }
+    Ok((game_id, game))
+}
+
+/// Download the bot of a game and check it can be run, the language is found from the
+/// content of the file before its name and content type
+#[instrument(skip(runner))]
+async fn store_attachment(
+    attachment: &Attachment,
+    game_id: usize,
+    seed: u64,
+    runner: &Runner,
+) -> eyre::Result<PathBuf> {
+    if attachment.size > 100_000_000 {
+        return Err(Error::msg(format!(
+            "File too big ({}>100MB)",
+            attachment.size
+        )));
+    }
+    let mut file_bytes = attachment.download().await?;
+    let languages = runner.languages();
+    if file_bytes.starts_with(ZIP_MAGIC) {
+        // Inflating a bundle can take a while, it must not block the other games
+        let bundle = tokio::task::spawn_blocking(move || read_bundle(&file_bytes)).await??;
+        let entry = bundle.entry.to_string_lossy().into_owned();
+        let language = languages.find(None, &entry).ok_or(Error::msg(format!(
+            "Entry point {} has no known extension, bots can be written in {}",
+            entry,
+            languages.describe()
+        )))?;
+        if language.runtime.is_none() {
+            if let Some((_, entry_bytes)) =
+                bundle.files.iter().find(|(path, _)| *path == bundle.entry)
+            {
+                check_wasm(entry_bytes)?;
+            }
+        }
+        let path = store_bundle(bundle, game_id).await?;
+        return check_stored_file(runner, game_id, seed, path, language).await;
+    }
+
+    let language = if file_bytes.starts_with(WASM_MAGIC) {
+        languages.by_extension("wasm")
+    } else if is_wat(&file_bytes, &attachment.filename) {
+        file_bytes = compile_wat(&file_bytes)?;
+        languages.by_extension("wasm")
+    } else {
+        languages.find(attachment.content_type.as_deref(), &attachment.filename)
+    }
+    .ok_or(Error::msg(format!(
+        "Attachment {} has bad content type {}, bots can be written in {}",
+        attachment.filename,
+        attachment.content_type.as_deref().unwrap_or("none"),
+        languages.describe()
+    )))?;
+    if language.runtime.is_none() {
+        check_wasm(&file_bytes)?;
+    }
+
+    let path = store_wasm_to_file(file_bytes.as_slice(), game_id, &language.extensions[0]).await?;
+    check_stored_file(runner, game_id, seed, path, language).await
+}
+
+/// Check the syntax of a stored bot and compile it, its folder is removed if it fails
+async fn check_stored_file(
+    runner: &Runner,
+    game_id: usize,
+    seed: u64,
+    path: PathBuf,
+    language: &Language,
+) -> eyre::Result<PathBuf> {
+    let checked = match runner.check_source(game_id, &path, language).await {
+        Ok(()) => runner.precompile(game_id, &path, seed).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = checked {
+        if let Some(directory) = path.parent() {
+            tokio::fs::remove_dir_all(directory).await?;
+            runner.forget(directory)?;
+        }
+        return Err(error);
+    }
+    Ok(path)
+}
+
 #[instrument]
 fn get_attachment(command: &ApplicationCommandInteraction) -> Option<&Attachment> {
-    if let CommandDataOptionValue::Attachment(attachment) =
-        command.data.options.get(0)?.resolved.as_ref()?
+    if let CommandDataOptionValue::Attachment(attachment) = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "attachment")?
+        .resolved
+        .as_ref()?
     {
         Some(attachment)
     } else {
@@ -105,10 +239,17 @@ fn get_attachment(command: &ApplicationCommandInteraction) -> Option<&Attachment
 }
 
 #[instrument]
-fn get_settings(command: &ApplicationCommandInteraction) -> MinesweeperSettings {
+pub fn get_settings(command: &ApplicationCommandInteraction) -> MinesweeperSettings {
     let mut width = 8;
     let mut height = 8;
     let mut bomb_probability = 0.2;
+    let mut renderer = Renderer::Emoji;
+    let mut session = false;
+    let mut scratch = false;
+    let mut seed = None;
+    let mut rules = StrikeRules::default();
+    let mut time = None;
+    let mut increment = None;
     for option in command.data.options.iter() {
         match option.name.as_str() {
             "width" => {
@@ -128,6 +269,54 @@ fn get_settings(command: &ApplicationCommandInteraction) -> MinesweeperSettings
                     bomb_probability = bomb_probability_desired.clamp(0.1, 0.9)
                 }
             }
+            "renderer" => {
+                if let Some(CommandDataOptionValue::String(renderer_desired)) = &option.resolved {
+                    renderer = match renderer_desired.as_str() {
+                        "ansi" => Renderer::Ansi,
+                        _ => Renderer::Emoji,
+                    };
+                }
+            }
+            "session" => {
+                if let Some(CommandDataOptionValue::Boolean(session_desired)) = option.resolved {
+                    session = session_desired;
+                }
+            }
+            "scratch" => {
+                if let Some(CommandDataOptionValue::Boolean(scratch_desired)) = option.resolved {
+                    scratch = scratch_desired;
+                }
+            }
+            "seed" => {
+                if let Some(CommandDataOptionValue::Integer(seed_desired)) = option.resolved {
+                    seed = Some(seed_desired.clamp(0, MAX_SEED as i64) as u64);
+                }
+            }
+            "strikes" => {
+                if let Some(CommandDataOptionValue::Integer(strikes_desired)) = option.resolved {
+                    rules.max_strikes = strikes_desired.clamp(1, 10) as usize;
+                }
+            }
+            "penalty" => {
+                if let Some(CommandDataOptionValue::String(penalty_desired)) = &option.resolved {
+                    rules.penalty = match penalty_desired.as_str() {
+                        "reveal" => Penalty::SafeReveal,
+                        _ => Penalty::Skip,
+                    };
+                }
+            }
+            "time" => {
+                if let Some(CommandDataOptionValue::Integer(time_desired)) = option.resolved {
+                    time = Some(Duration::from_secs(time_desired.clamp(1, 3600) as u64));
+                }
+            }
+            "increment" => {
+                if let Some(CommandDataOptionValue::Integer(increment_desired)) = option.resolved {
+                    increment = Some(Duration::from_millis(
+                        increment_desired.clamp(0, 60_000) as u64
+                    ));
+                }
+            }
             _ => (),
         }
     }
@@ -135,6 +324,13 @@ fn get_settings(command: &ApplicationCommandInteraction) -> MinesweeperSettings
         width,
         height,
         bomb_probability,
+        renderer,
+        session,
+        scratch,
+        seed,
+        rules,
+        time,
+        increment,
     }
 }
 
@@ -144,7 +340,37 @@ async fn store_wasm_to_file(
     game_id: usize,
     extension: &str,
 ) -> eyre::Result<PathBuf> {
-    let path = PathBuf::from(format!("./tmp/{}.{}", game_id, extension));
+    let directory = create_game_directory(game_id).await?;
+    let path = directory.join(format!("bot.{}", extension));
     tokio::fs::write(path.as_path(), bytes).await?;
     Ok(path)
 }
+
+/// Extract the files of a bundle in the folder of a game and return the path of its entry point
+#[instrument(skip(bundle))]
+async fn store_bundle(bundle: Bundle, game_id: usize) -> eyre::Result<PathBuf> {
+    let directory = create_game_directory(game_id).await?;
+    if let Err(error) = write_bundle_files(&directory, bundle.files).await {
+        tokio::fs::remove_dir_all(&directory).await?;
+        return Err(error);
+    }
+    Ok(directory.join(bundle.entry))
+}
+
+async fn write_bundle_files(directory: &Path, files: Vec<(PathBuf, Vec<u8>)>) -> eyre::Result<()> {
+    for (path, content) in files {
+        let path = directory.join(path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, content).await?;
+    }
+    Ok(())
+}
+
+/// Each bot has its own folder, mapped read-only in the guest
+async fn create_game_directory(game_id: usize) -> eyre::Result<PathBuf> {
+    let directory = PathBuf::from(format!("./tmp/{}", game_id));
+    tokio::fs::create_dir_all(directory.as_path()).await?;
+    Ok(directory)
+}
@@ -0,0 +1,401 @@
+use crate::protocol::Protocol;
+use crate::runner::determinism::MAX_SEED;
+use crate::runner::transcript::new_transcript_key;
+use crate::runner::{BotRun, Session};
+use minesweeper::{MinesweeperCellType, MinesweeperGrid};
+use serenity::model::id::UserId;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Renderer {
+    Emoji,
+    Ansi,
+}
+
+impl Renderer {
+    /// Size of the window shown for grids too big to fit in one message
+    pub fn viewport_size(self) -> usize {
+        match self {
+            Renderer::Emoji => 12,
+            Renderer::Ansi => 24,
+        }
+    }
+}
+
+/// Characters of a game message left for its grid, its minimap and its position, out of the 2000
+/// of a Discord message
+pub const MAX_GRID_LENGTH: usize = 1500;
+/// Number of lines and columns removed from a window rendered above `MAX_GRID_LENGTH`
+pub const VIEWPORT_STEP: usize = 4;
+
+/// Number of characters on each side of the minimap of grids bigger than their window
+pub const MINIMAP_SIZE: usize = 16;
+
+/// Number of lines of cell buttons shown at once in human mode
+pub const CELL_PAGE_WIDTH: usize = 4;
+/// Number of cell buttons on each line in human mode
+pub const CELL_PAGE_HEIGHT: usize = 5;
+
+/// Points taken from the score of a bot for each strike
+pub const STRIKE_COST: i64 = 10;
+
+/// What happens to the turn of a bot that failed to play
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Penalty {
+    /// The turn is lost
+    Skip,
+    /// A random safe cell is revealed instead, so the game goes on
+    SafeReveal,
+}
+
+/// How a game treats the bots that time out, crash or play invalid moves
+#[derive(Debug, Copy, Clone)]
+pub struct StrikeRules {
+    /// Number of strikes after which the bot forfeits
+    pub max_strikes: usize,
+    pub penalty: Penalty,
+}
+
+impl Default for StrikeRules {
+    fn default() -> Self {
+        Self {
+            max_strikes: 3,
+            penalty: Penalty::Skip,
+        }
+    }
+}
+
+/// Thinking time of a bot for a whole game, before the time of each cell
+pub const CLOCK_BASE: Duration = Duration::from_secs(10);
+/// Thinking time added for each cell of the grid, so big grids get more time
+pub const CLOCK_PER_CELL: Duration = Duration::from_millis(20);
+/// Time credited to a bot after each move
+pub const CLOCK_INCREMENT: Duration = Duration::from_secs(1);
+
+/// Chess clock of a bot: the thinking time it has left for the rest of the game, credited with
+/// an increment after each move
+#[derive(Debug, Copy, Clone)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+}
+
+impl Clock {
+    /// Default clock of a grid, scaled by its number of cells
+    pub fn for_grid(width: usize, height: usize) -> Self {
+        Self {
+            remaining: CLOCK_BASE + CLOCK_PER_CELL * (width * height) as u32,
+            increment: CLOCK_INCREMENT,
+        }
+    }
+
+    /// Take the time of a move from the clock, and credit the increment if time is left
+    pub fn spend(&mut self, time: Duration) {
+        self.remaining = self.remaining.saturating_sub(time);
+        if !self.is_out() {
+            self.remaining += self.increment;
+        }
+    }
+
+    pub fn is_out(&self) -> bool {
+        self.remaining.is_zero()
+    }
+}
+
+pub enum Player {
+    /// The uploaded file at this path plays
+    Bot(PathBuf),
+    /// The owner of the game plays with the cell buttons
+    Human,
+}
+
+pub struct Game {
+    pub grid: MinesweeperGrid,
+    pub player: Player,
+    /// User who started the game
+    pub owner: UserId,
+    pub renderer: Renderer,
+    pub last_move: Option<(usize, usize)>,
+    /// Protocol spoken by the bot, known after its first move
+    pub protocol: Option<Protocol>,
+    /// Number of moves played by the bot
+    pub moves: usize,
+    /// Output of the bot for the last move
+    pub last_run: Option<BotRun>,
+    /// Whether the bot is kept running between moves instead of being run for each move
+    pub session_mode: bool,
+    /// Bot running for this game in session mode
+    pub session: Option<Session>,
+    /// First position of the window of each user who panned the grid
+    pub viewports: HashMap<UserId, (usize, usize)>,
+    /// In human mode, whether pressing a cell puts a flag instead of revealing it
+    pub flag_mode: bool,
+    /// In human mode, index of the page of cell buttons shown
+    pub page: usize,
+    /// Background task playing the bot until the game ends
+    pub autoplay: Option<JoinHandle<()>>,
+    /// Directory where the bot keeps what it learns between games, if the owner opted in
+    pub scratch: Option<PathBuf>,
+    /// Seed of the board and of the random bytes given to the bot, the same seed gives the same
+    /// moves
+    pub seed: u64,
+    pub rules: StrikeRules,
+    /// Number of turns the bot failed to play
+    pub strikes: usize,
+    /// Cells revealed by the penalty of the strikes, not counted in the score
+    pub penalty_revealed: usize,
+    /// Why the bot failed its last turn, cleared once it plays a valid move
+    pub last_strike: Option<String>,
+    pub clock: Clock,
+    /// Whether the moves of the bot are limited by its clock, instead of fuel
+    pub timed: bool,
+    /// Key of the transcript of the bot, which outlives the game and its id
+    pub transcript: String,
+}
+
+impl Game {
+    pub fn new(grid: MinesweeperGrid, player: Player, owner: UserId, renderer: Renderer) -> Self {
+        let clock = Clock::for_grid(grid.width(), grid.height());
+        Self {
+            grid,
+            player,
+            owner,
+            renderer,
+            last_move: None,
+            protocol: None,
+            moves: 0,
+            last_run: None,
+            session_mode: false,
+            session: None,
+            viewports: HashMap::new(),
+            flag_mode: false,
+            page: 0,
+            autoplay: None,
+            scratch: None,
+            seed: rand::random::<u64>() & MAX_SEED,
+            rules: StrikeRules::default(),
+            strikes: 0,
+            penalty_revealed: 0,
+            last_strike: None,
+            clock,
+            timed: true,
+            transcript: new_transcript_key(),
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.grid.width().div_ceil(CELL_PAGE_WIDTH) * self.grid.height().div_ceil(CELL_PAGE_HEIGHT)
+    }
+
+    /// First position of the cells of the current page
+    pub fn page_start(&self) -> (usize, usize) {
+        let pages_per_line = self.grid.height().div_ceil(CELL_PAGE_HEIGHT);
+        (
+            (self.page / pages_per_line) * CELL_PAGE_WIDTH,
+            (self.page % pages_per_line) * CELL_PAGE_HEIGHT,
+        )
+    }
+
+    /// Whether the bot had as many strikes as the rules allow
+    pub fn is_forfeited(&self) -> bool {
+        self.strikes >= self.rules.max_strikes
+    }
+
+    /// Score of a bot: the cells it revealed, minus `STRIKE_COST` per strike
+    pub fn score(&self) -> i64 {
+        let revealed = (0..self.grid.width())
+            .flat_map(|xpos| (0..self.grid.height()).map(move |ypos| (xpos, ypos)))
+            .filter(|&(xpos, ypos)| {
+                matches!(
+                    self.grid.get_cell(xpos, ypos),
+                    Some(MinesweeperCellType::Found(_))
+                )
+            })
+            .count()
+            - self.penalty_revealed;
+        revealed as i64 - STRIKE_COST * self.strikes as i64
+    }
+
+    pub fn needs_viewport(&self) -> bool {
+        let viewport_size = self.renderer.viewport_size();
+        self.grid.width() > viewport_size || self.grid.height() > viewport_size
+    }
+
+    /// Window of the game message, centred on the last move of a bot or on the cell buttons
+    /// of a human
+    pub fn main_viewport(&self) -> (usize, usize) {
+        let (xpos, ypos) = match self.player {
+            Player::Bot(_) => self
+                .last_move
+                .unwrap_or((self.grid.width() / 2, self.grid.height() / 2)),
+            Player::Human => {
+                let (xstart, ystart) = self.page_start();
+                (xstart + CELL_PAGE_WIDTH / 2, ystart + CELL_PAGE_HEIGHT / 2)
+            }
+        };
+        let viewport_size = self.renderer.viewport_size();
+        self.grid
+            .window_around(xpos, ypos, viewport_size, viewport_size)
+    }
+
+    /// Move the window of a user by half a window and return its new position
+    pub fn pan(&mut self, user_id: UserId, xdirection: isize, ydirection: isize) -> (usize, usize) {
+        let (xstart, ystart) = self
+            .viewports
+            .get(&user_id)
+            .copied()
+            .unwrap_or_else(|| self.main_viewport());
+        let viewport_size = self.renderer.viewport_size();
+        let step = (viewport_size / 2) as isize;
+        let xstart = xstart
+            .saturating_add_signed(xdirection * step)
+            .min(self.grid.width().saturating_sub(viewport_size));
+        let ystart = ystart
+            .saturating_add_signed(ydirection * step)
+            .min(self.grid.height().saturating_sub(viewport_size));
+        self.viewports.insert(user_id, (xstart, ystart));
+        (xstart, ystart)
+    }
+
+    /// Render the window of the grid, bombs are hidden when a human is playing
+    pub fn to_discord_string(&self, viewport: (usize, usize)) -> String {
+        match self.player {
+            Player::Bot(_) => self.to_discord_string_of(&self.grid, viewport),
+            Player::Human => self.to_discord_string_of(&self.grid.player_view(), viewport),
+        }
+    }
+
+    /// Render a window of a grid, shrunk around its centre while the rendering is longer than
+    /// `MAX_GRID_LENGTH`, since the colours of an ANSI window make its length depend on its cells
+    fn to_discord_string_of(
+        &self,
+        grid: &MinesweeperGrid,
+        (xstart, ystart): (usize, usize),
+    ) -> String {
+        let viewport_size = self.renderer.viewport_size();
+        let mut size = viewport_size;
+        loop {
+            let offset = (viewport_size - size) / 2;
+            let window = (
+                (xstart + offset).min(self.grid.width().saturating_sub(size)),
+                (ystart + offset).min(self.grid.height().saturating_sub(size)),
+            );
+            let rendered = self.to_discord_window_of(grid, window, size);
+            // Discord counts the length of messages in UTF-16 code units
+            if size <= VIEWPORT_STEP || rendered.encode_utf16().count() <= MAX_GRID_LENGTH {
+                return rendered;
+            }
+            size -= VIEWPORT_STEP;
+        }
+    }
+
+    fn to_discord_window_of(
+        &self,
+        grid: &MinesweeperGrid,
+        (xstart, ystart): (usize, usize),
+        size: usize,
+    ) -> String {
+        let grid_string = match self.renderer {
+            Renderer::Emoji => grid.to_discord_string_window(xstart, ystart, size, size),
+            Renderer::Ansi => grid.to_discord_ansi_string_window(xstart, ystart, size, size),
+        };
+        if self.grid.width() <= size && self.grid.height() <= size {
+            return grid_string;
+        }
+        let xend = (xstart + size).min(self.grid.width());
+        let yend = (ystart + size).min(self.grid.height());
+        format!(
+            "{}\n{}\n🗺️ Lines {}-{} of {} | Columns {}-{} of {}",
+            grid_string,
+            grid.to_minimap_string(MINIMAP_SIZE, (xstart, ystart, size, size)),
+            xstart,
+            xend - 1,
+            self.grid.width(),
+            ystart,
+            yend - 1,
+            self.grid.height()
+        )
+    }
+
+    /// Content of the message of a game being played
+    pub fn to_discord_message(&self, game_id: usize) -> String {
+        let mut content = format!(
+            "# Minesweeper {}\n{}",
+            game_id,
+            self.to_discord_string(self.main_viewport())
+        );
+        if let Some(BotRun { stats, .. }) = &self.last_run {
+            content.push_str(&format!(
+                "\n⏱️ Last move took {} ms",
+                stats.wall_time.as_millis()
+            ));
+            if let Some(fuel) = stats.fuel {
+                content.push_str(&format!(" and {} fuel", fuel));
+            }
+        }
+        if let Player::Bot(_) = self.player {
+            content.push_str(&format!("\n🎲 Seed {}", self.seed));
+            if self.timed {
+                content.push_str(&format!(
+                    " | ⏳ {:.1} s left (+{:.1} s per move)",
+                    self.clock.remaining.as_secs_f64(),
+                    self.clock.increment.as_secs_f64()
+                ));
+            }
+            if self.strikes > 0 {
+                content.push_str(&format!(
+                    "\n❌ Strikes {}/{}",
+                    self.strikes, self.rules.max_strikes
+                ));
+            }
+            if let Some(reason) = &self.last_strike {
+                content.push_str(&format!(" | Last turn failed: {}", reason));
+            }
+        }
+        if let Player::Human = self.player {
+            content.push_str(&format!(
+                "\n{} | Page {}/{}",
+                if self.flag_mode {
+                    "🚩 Flag mode"
+                } else {
+                    "🔍 Reveal mode"
+                },
+                self.page + 1,
+                self.page_count()
+            ));
+        }
+        content
+    }
+
+    /// Content of the message of a game that is over
+    pub fn to_discord_ended_message(&self) -> String {
+        let mut content = format!(
+            "# Minesweeper ENDED\n{}\n{}",
+            self.to_discord_string_of(&self.grid, self.main_viewport()),
+            if self.grid.is_won() {
+                "🏆 Won"
+            } else if self.is_forfeited() {
+                "🏳️ Forfeited"
+            } else if self.clock.is_out() {
+                "⌛ Lost on time"
+            } else {
+                "💥 Lost"
+            }
+        );
+        if let Player::Bot(_) = self.player {
+            content.push_str(&format!(
+                "\n🏅 Score {} | ❌ Strikes {}/{}",
+                self.score(),
+                self.strikes,
+                self.rules.max_strikes
+            ));
+            if let Some(reason) = self.last_strike.as_ref().filter(|_| self.is_forfeited()) {
+                content.push_str(&format!("\nLast turn failed: {}", reason));
+            }
+        }
+        content
+    }
+}
@@ -1,10 +1,17 @@
 #![feature(let_chains)]
 
+mod bundle;
 mod discord_command;
+mod game;
+mod protocol;
+mod runner;
 
+use game::Game;
 use konst::primitive::parse_u64;
 use konst::unwrap_ctx;
-use minesweeper::MinesweeperGrid;
+use runner::languages::Languages;
+use runner::transcript::{expire_transcripts, TRANSCRIPT_DIR};
+use runner::{Runner, RunnerLimits};
 use serenity::async_trait;
 use serenity::client::Context;
 use serenity::model::application::interaction::MessageFlags;
@@ -12,18 +19,22 @@ use serenity::model::gateway::Ready;
 use serenity::model::prelude::{GuildId, Interaction, InteractionResponseType};
 use serenity::prelude::{Client, EventHandler, GatewayIntents};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::Path;
 use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 use tokio::fs::{create_dir_all, remove_dir_all};
 use tokio::sync::{Mutex, RwLock};
 use tracing::instrument;
 
 const TOKEN: &str = include_str!("../token.txt");
 const APPLICATION_ID: u64 = unwrap_ctx!(parse_u64(include_str!("../application_id.txt")));
+/// Languages the bots can be written in
+const RUNNERS_CONFIG: &str = "./runners.toml";
 
 pub struct Handler {
     pub number_grid: AtomicUsize,
-    pub grids: RwLock<HashMap<usize, Mutex<(MinesweeperGrid, PathBuf)>>>,
+    pub grids: Arc<RwLock<HashMap<usize, Arc<Mutex<Game>>>>>,
+    pub runner: Runner,
 }
 
 #[async_trait]
@@ -38,6 +49,12 @@ impl EventHandler for Handler {
             let commands = GuildId::set_application_commands(&guild_id, &ctx.http, |commands| {
                 commands
                     .create_application_command(|command| discord_command::create_command(command))
+                    .create_application_command(|command| {
+                        discord_command::create_spoiler_command(command)
+                    })
+                    .create_application_command(|command| {
+                        discord_command::create_scratch_command(command)
+                    })
             })
             .await;
 
@@ -50,64 +67,87 @@ impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
             Interaction::ApplicationCommand(command) => {
-                if command.data.name.as_str() == "start" {
-                    if let Err(error) =
-                        discord_command::start::start_command(self, &ctx, &command).await
+                let result = match command.data.name.as_str() {
+                    "start" => discord_command::start::start_command(self, &ctx, &command).await,
+                    "spoiler" => discord_command::spoiler::spoiler_command(&ctx, &command).await,
+                    "scratch" => {
+                        discord_command::scratch::scratch_command(self, &ctx, &command).await
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(error) = result {
+                    if let Err(why) = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    message.content(error).flags(MessageFlags::EPHEMERAL)
+                                })
+                        })
+                        .await
                     {
-                        if let Err(why) = command
-                            .create_interaction_response(&ctx.http, |response| {
-                                response
-                                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                                    .interaction_response_data(|message| {
-                                        message.content(error).flags(MessageFlags::EPHEMERAL)
-                                    })
-                            })
-                            .await
-                        {
-                            dbg!("Error start: {}", why);
-                        }
+                        dbg!("Error {}: {}", command.data.name.as_str(), why);
                     }
                 }
             }
-            Interaction::MessageComponent(command) => match command.data.custom_id.as_str() {
-                "play_button_id" => {
-                    if let Err(error) =
+            Interaction::MessageComponent(command) => {
+                let result = match command.data.custom_id.as_str() {
+                    "play_button_id" => {
                         discord_command::buttons::play::play_button(self, &ctx, &command).await
-                    {
-                        if let Err(why) = command
-                            .create_interaction_response(&ctx.http, |response| {
-                                response
-                                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                                    .interaction_response_data(|message| {
-                                        message.content(error).flags(MessageFlags::EPHEMERAL)
-                                    })
-                            })
+                    }
+                    "autoplay_button_id" => {
+                        discord_command::buttons::autoplay::autoplay_button(self, &ctx, &command)
                             .await
-                        {
-                            dbg!("Error play: {}", why);
-                        }
                     }
-                }
-                "quit_button_id" => {
-                    if let Err(error) =
+                    "pause_button_id" => {
+                        discord_command::buttons::autoplay::pause_button(self, &ctx, &command).await
+                    }
+                    "step_select_id" => {
+                        discord_command::buttons::step::step_select(self, &ctx, &command).await
+                    }
+                    "bot_output_button_id" => {
+                        discord_command::buttons::output::bot_output_button(self, &ctx, &command)
+                            .await
+                    }
+                    custom_id if custom_id.starts_with("transcript_") => {
+                        discord_command::buttons::transcript::transcript_button(&ctx, &command)
+                            .await
+                    }
+                    "quit_button_id" => {
                         discord_command::buttons::quit::quit_button(self, &ctx, &command).await
+                    }
+                    "mode_button_id" => {
+                        discord_command::buttons::human::mode_button(self, &ctx, &command).await
+                    }
+                    "page_previous_button_id" | "page_next_button_id" => {
+                        discord_command::buttons::human::page_button(self, &ctx, &command).await
+                    }
+                    custom_id if custom_id.starts_with("cell_") => {
+                        discord_command::buttons::human::cell_button(self, &ctx, &command).await
+                    }
+                    "pan_up_button_id"
+                    | "pan_down_button_id"
+                    | "pan_left_button_id"
+                    | "pan_right_button_id" => {
+                        discord_command::buttons::pan::pan_button(self, &ctx, &command).await
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(error) = result {
+                    if let Err(why) = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    message.content(error).flags(MessageFlags::EPHEMERAL)
+                                })
+                        })
+                        .await
                     {
-                        if let Err(why) = command
-                            .create_interaction_response(&ctx.http, |response| {
-                                response
-                                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                                    .interaction_response_data(|message| {
-                                        message.content(error).flags(MessageFlags::EPHEMERAL)
-                                    })
-                            })
-                            .await
-                        {
-                            dbg!("Error quit: {}", why);
-                        }
+                        dbg!("Error {}: {}", command.data.custom_id.as_str(), why);
                     }
                 }
-                _ => (),
-            },
+            }
             _ => (),
         }
     }
@@ -120,18 +160,40 @@ async fn main() {
 
     let _ = remove_dir_all("./tmp/").await;
     create_dir_all("./tmp/").await.unwrap();
+    create_dir_all(TRANSCRIPT_DIR).await.unwrap();
+    tokio::spawn(expire_transcripts());
 
     let intents = GatewayIntents::empty();
+    let languages = Languages::load(Path::new(RUNNERS_CONFIG)).expect("Error loading the runners");
+    let runner =
+        Runner::new(RunnerLimits::default(), languages).expect("Error creating the runner");
+    // The runtimes are compiled once, before the first game
+    let cache_runner = runner.clone();
+    tokio::task::spawn_blocking(move || cache_runner.prepare_cache())
+        .await
+        .unwrap()
+        .expect("Error preparing the compilation cache");
 
     let mut client = Client::builder(TOKEN, intents)
         .event_handler(Handler {
             number_grid: AtomicUsize::new(0),
-            grids: RwLock::new(HashMap::with_capacity(10)),
+            grids: Arc::new(RwLock::new(HashMap::with_capacity(10))),
+            runner: runner.clone(),
         })
         .application_id(APPLICATION_ID)
         .await
         .expect("Error creating the client");
 
+    // Kill the running bots before shutting down
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Error listening for Ctrl-C");
+        runner.kill_all();
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(why) = client.start().await {
         dbg!("Error: {}", why);
     }
@@ -0,0 +1,375 @@
+use crate::game::Game;
+use eyre::Error;
+use minesweeper::MinesweeperCellType;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::instrument;
+
+/// Version of the JSON protocol, declared by bots on their first output line
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Output lines starting with one of these, ignoring the case, are debug prints skipped by the
+/// parser
+pub const DEBUG_PREFIXES: [&str; 4] = ["#", "//", "debug:", "[debug]"];
+/// Maximum number of characters of an output line quoted in a parse error
+const MAX_QUOTED_LENGTH: usize = 100;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// The console board as input and `(x,y)` as output
+    Console,
+    /// A JSON state as input and JSON actions as output
+    Json,
+    /// A component exporting `next-move`, see `wit/minesweeper-bot.wit`
+    Component,
+}
+
+/// First output line of a bot speaking the JSON protocol, like `{"protocol":1}`
+#[derive(Deserialize)]
+struct Declaration {
+    protocol: u32,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    Reveal {
+        x: usize,
+        y: usize,
+    },
+    Flag {
+        x: usize,
+        y: usize,
+    },
+    Unflag {
+        x: usize,
+        y: usize,
+    },
+    /// Reveal the neighbours of a revealed cell whose bombs are all flagged
+    Chord {
+        x: usize,
+        y: usize,
+    },
+}
+
+impl Action {
+    pub fn position(self) -> (usize, usize) {
+        match self {
+            Action::Reveal { x, y }
+            | Action::Flag { x, y }
+            | Action::Unflag { x, y }
+            | Action::Chord { x, y } => (x, y),
+        }
+    }
+}
+
+/// A bot can answer one action or several actions played in order
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Actions {
+    Many { actions: Vec<Action> },
+    One(Action),
+}
+
+/// State of the game given to the bot for its next move
+#[derive(Serialize)]
+pub struct State {
+    pub protocol: u32,
+    pub width: usize,
+    pub height: usize,
+    /// One string per line, `_` for hidden cells, `F` for flags and the number of neighbour
+    /// bombs for revealed cells
+    pub cells: Vec<String>,
+    pub flags: Vec<(usize, usize)>,
+    pub mines_remaining: i64,
+    #[serde(rename = "move")]
+    pub move_number: usize,
+    /// Time left on the clock of the bot for the rest of the game, `null` when it is limited
+    /// with fuel
+    pub time_left_ms: Option<u128>,
+}
+
+pub struct BotOutput {
+    /// Protocol declared on the first output line
+    pub declared: Option<Protocol>,
+    pub actions: Vec<Action>,
+}
+
+pub fn game_state(game: &Game, time_left: Option<Duration>) -> State {
+    let mut cells = Vec::with_capacity(game.grid.width());
+    let mut flags = Vec::new();
+    for xpos in 0..game.grid.width() {
+        let mut line = String::with_capacity(game.grid.height());
+        for ypos in 0..game.grid.height() {
+            let cell = game
+                .grid
+                .get_cell(xpos, ypos)
+                .copied()
+                .unwrap_or(MinesweeperCellType::Hidden);
+            line.push(match cell {
+                MinesweeperCellType::Found(n) => (b'0' + n) as char,
+                MinesweeperCellType::Flagged | MinesweeperCellType::BombFlagged => 'F',
+                _ => '_',
+            });
+            if cell.is_flagged() {
+                flags.push((xpos, ypos));
+            }
+        }
+        cells.push(line);
+    }
+    State {
+        protocol: PROTOCOL_VERSION,
+        width: game.grid.width(),
+        height: game.grid.height(),
+        cells,
+        mines_remaining: game.grid.bomb_count() as i64 - flags.len() as i64,
+        flags,
+        move_number: game.moves,
+        time_left_ms: time_left.map(|time_left| time_left.as_millis()),
+    }
+}
+
+/// Input given on stdin to the bot for its next move
+pub fn encode_input(game: &Game, protocol: Protocol, time_left: Option<Duration>) -> String {
+    match protocol {
+        Protocol::Console => game.grid.to_console_string(),
+        Protocol::Json | Protocol::Component => {
+            serde_json::to_string(&game_state(game, time_left)).unwrap_or_default()
+        }
+    }
+}
+
+/// Parse the output of a bot, starting with an optional protocol declaration, and check its
+/// actions are inside a grid of `width` x `height` cells
+#[instrument]
+pub fn parse_output(
+    stdout: &str,
+    protocol: Protocol,
+    width: usize,
+    height: usize,
+) -> eyre::Result<BotOutput> {
+    let mut lines = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !is_debug_line(line))
+        .peekable();
+
+    let mut declared = None;
+    if let Some(Ok(Declaration { protocol: version })) =
+        lines.peek().map(|line| serde_json::from_str(line))
+    {
+        if version != PROTOCOL_VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported protocol version {}, expected {}",
+                version, PROTOCOL_VERSION
+            )));
+        }
+        declared = Some(Protocol::Json);
+        lines.next();
+    }
+
+    let actions = match (lines.peek(), declared.unwrap_or(protocol)) {
+        (None, _) => vec![],
+        (Some(_), Protocol::Console) => {
+            let (x, y) = find_move(lines)?;
+            vec![Action::Reveal { x, y }]
+        }
+        (Some(line), Protocol::Json | Protocol::Component) => match serde_json::from_str(line)
+            .map_err(|error| Error::msg(format!("Bad actions {}: {}", quote_output(line), error)))?
+        {
+            Actions::Many { actions } => actions,
+            Actions::One(action) => vec![action],
+        },
+    };
+
+    for action in actions.iter() {
+        let (x, y) = action.position();
+        if x >= width || y >= height {
+            return Err(Error::msg(format!(
+                "Move ({},{}) is outside of the {}x{} grid, coordinates start at 0",
+                x, y, width, height
+            )));
+        }
+    }
+
+    Ok(BotOutput { declared, actions })
+}
+
+/// Whether a line of the output is a debug print, starting with one of `DEBUG_PREFIXES`
+fn is_debug_line(line: &str) -> bool {
+    DEBUG_PREFIXES.iter().any(|prefix| {
+        line.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    })
+}
+
+/// Move of a bot speaking the console protocol, on the first line that is one
+fn find_move<'a>(lines: impl Iterator<Item = &'a str>) -> eyre::Result<(usize, usize)> {
+    let mut last_error = None;
+    for line in lines {
+        match parse_move(line) {
+            Ok(position) => return Ok(position),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or(Error::msg("The bot did not print its move")))
+}
+
+/// Parse a move like `(x,y)`, `x,y`, `x y` or `[x, y]`
+#[instrument]
+fn parse_move(line: &str) -> eyre::Result<(usize, usize)> {
+    let unwrapped = line
+        .strip_prefix('(')
+        .and_then(|line| line.strip_suffix(')'))
+        .or_else(|| {
+            line.strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+        })
+        .unwrap_or(line);
+    let coordinates = unwrapped
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|coordinate| !coordinate.is_empty())
+        .collect::<Vec<&str>>();
+    let not_a_move = || {
+        Error::msg(format!(
+            "Expected a move like (x,y), the bot printed {}",
+            quote_output(line)
+        ))
+    };
+    let [xpos, ypos] = coordinates[..] else {
+        return Err(not_a_move());
+    };
+    // Words are not a move, rather than a move with bad coordinates
+    if ![xpos, ypos]
+        .iter()
+        .any(|coordinate| coordinate.starts_with(|c: char| c.is_ascii_digit() || c == '-'))
+    {
+        return Err(not_a_move());
+    }
+    let parse_coordinate = |name: &str, coordinate: &str| {
+        coordinate.parse().map_err(|_| {
+            Error::msg(format!(
+                "{} is not a coordinate in the move {}, expected a number from 0",
+                name,
+                quote_output(line)
+            ))
+        })
+    };
+    Ok((parse_coordinate("x", xpos)?, parse_coordinate("y", ypos)?))
+}
+
+/// A line of the output between backquotes, shortened to `MAX_QUOTED_LENGTH` characters
+fn quote_output(line: &str) -> String {
+    let line = line.replace('`', "'");
+    if line.chars().count() <= MAX_QUOTED_LENGTH {
+        return format!("`{}`", line);
+    }
+    format!(
+        "`{}...`",
+        line.chars().take(MAX_QUOTED_LENGTH).collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console_move(stdout: &str) -> eyre::Result<(usize, usize)> {
+        let output = parse_output(stdout, Protocol::Console, 10, 10)?;
+        Ok(output.actions[0].position())
+    }
+
+    fn console_error(stdout: &str) -> String {
+        match console_move(stdout) {
+            Ok(position) => panic!("{:?} was parsed as {:?}", stdout, position),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn console_moves_are_parsed() {
+        assert_eq!(console_move("3,4").unwrap(), (3, 4));
+        assert_eq!(console_move("3 4\n").unwrap(), (3, 4));
+        assert_eq!(console_move("[3,4]").unwrap(), (3, 4));
+        assert_eq!(console_move("  (3, 4)  ").unwrap(), (3, 4));
+        assert_eq!(console_move("(3; 4)").unwrap(), (3, 4));
+    }
+
+    #[test]
+    fn first_move_line_is_played() {
+        assert_eq!(console_move("(3, 4)\n(5, 6)\nbye").unwrap(), (3, 4));
+        assert_eq!(console_move("thinking\n\n(1,2)\n(5,6)").unwrap(), (1, 2));
+        assert!(parse_output("", Protocol::Console, 10, 10)
+            .unwrap()
+            .actions
+            .is_empty());
+    }
+
+    #[test]
+    fn debug_lines_are_skipped() {
+        for prefix in DEBUG_PREFIXES {
+            let stdout = format!("{} (1,1)\n(2,3)", prefix);
+            assert_eq!(console_move(&stdout).unwrap(), (2, 3), "{}", prefix);
+            let stdout = format!("{} (1,1)\n(2,3)", prefix.to_uppercase());
+            assert_eq!(console_move(&stdout).unwrap(), (2, 3), "{}", prefix);
+        }
+        let output = parse_output("# only debug\n// prints", Protocol::Console, 10, 10).unwrap();
+        assert!(output.actions.is_empty());
+    }
+
+    #[test]
+    fn bad_coordinates_are_errors() {
+        assert!(console_error("(-1,2)").starts_with("x is not a coordinate"));
+        assert!(console_error("(3,-2)").starts_with("y is not a coordinate"));
+        assert!(console_error("3,b").starts_with("y is not a coordinate"));
+        assert!(console_error("[x,y]").starts_with("Expected a move like (x,y)"));
+        assert!(console_error("hello world").starts_with("Expected a move like (x,y)"));
+        assert!(console_error("1,2,3").starts_with("Expected a move like (x,y)"));
+    }
+
+    #[test]
+    fn moves_outside_of_the_grid_are_errors() {
+        assert!(console_error("(10,0)").starts_with("Move (10,0) is outside of the 10x10 grid"));
+        assert!(console_error("(0,10)").starts_with("Move (0,10) is outside of the 10x10 grid"));
+        let error = parse_output(r#"{"action":"flag","x":2,"y":7}"#, Protocol::Json, 10, 5)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("Move (2,7) is outside of the 10x5 grid"));
+    }
+
+    #[test]
+    fn long_output_is_truncated_in_errors() {
+        let line = "`a".repeat(200);
+        let error = console_error(&line);
+        let quoted = error.split_once('`').unwrap().1;
+        assert_eq!(
+            quoted,
+            format!("{}...`", "'a".repeat(MAX_QUOTED_LENGTH / 2))
+        );
+    }
+
+    #[test]
+    fn json_protocol_is_declared_and_parsed() {
+        let output = parse_output(
+            "{\"protocol\":1}\n{\"actions\":[{\"action\":\"flag\",\"x\":2,\"y\":2},{\"action\":\"chord\",\"x\":1,\"y\":1}]}",
+            Protocol::Console,
+            3,
+            3,
+        )
+        .unwrap();
+        assert_eq!(output.declared, Some(Protocol::Json));
+        assert_eq!(
+            output.actions,
+            vec![Action::Flag { x: 2, y: 2 }, Action::Chord { x: 1, y: 1 }]
+        );
+        let error = parse_output("{\"protocol\":2}", Protocol::Console, 3, 3)
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Unsupported protocol version 2"));
+        let error = parse_output("(1,1)", Protocol::Json, 3, 3).err().unwrap();
+        assert!(error.to_string().starts_with("Bad actions `(1,1)`"));
+    }
+}
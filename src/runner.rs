@@ -0,0 +1,780 @@
+use cache::Cache;
+use determinism::make_deterministic;
+use eyre::Error;
+use languages::{Language, Languages, BOT_GUEST_DIR};
+use scratch::{ScratchWatch, SCRATCH_GUEST_DIR};
+use snapshot::snapshot_path;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+use tracing::log::debug;
+use wasmtime::{Config, Engine, Linker, Store, StoreLimits, StoreLimitsBuilder, UpdateDeadline};
+use wasmtime_wasi::pipe::{AsyncReadStream, AsyncWriteStream, MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::WasiP1Ctx;
+use wasmtime_wasi::{
+    AsyncStdinStream, AsyncStdoutStream, DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+};
+
+pub mod cache;
+pub mod component;
+pub mod determinism;
+pub mod languages;
+pub mod scratch;
+pub mod snapshot;
+pub mod transcript;
+pub mod validate;
+
+/// Time between two increments of the epoch of the engine
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+/// Time a session waits for the next board before stopping the bot
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Time a session waits for more lines once the bot answered, the lines printed with the
+/// answer belonging to the same turn
+const SESSION_LINE_GRACE: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Copy, Clone)]
+pub enum CpuLimit {
+    /// Units of fuel a bot can consume for one move, about one per wasm instruction
+    Fuel(u64),
+    /// Time a bot can run when no clock is given, like the checks of the uploads, checked every
+    /// `EPOCH_TICK`. Moves are limited by the clock of their game
+    Epoch(Duration),
+}
+
+impl CpuLimit {
+    fn timeout(self) -> Option<Duration> {
+        match self {
+            CpuLimit::Fuel(_) => None,
+            CpuLimit::Epoch(duration) => Some(duration),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RunnerLimits {
+    pub cpu: CpuLimit,
+    /// Maximum size in bytes of the linear memory of a bot
+    pub memory: usize,
+    /// Maximum number of bytes a bot can write on stdout
+    pub stdout: usize,
+    /// Maximum number of bytes a bot can write on stderr
+    pub stderr: usize,
+    /// Wall-clock time of one run or one move in both CPU modes, so a bot limited with fuel or
+    /// waiting in a host call is still stopped
+    pub timeout: Duration,
+    /// Time a runtime can take to initialize before its snapshot
+    pub snapshot: Duration,
+    /// Maximum size in bytes of the scratch directory of a bot
+    pub scratch: u64,
+}
+
+impl Default for RunnerLimits {
+    fn default() -> Self {
+        Self {
+            cpu: CpuLimit::Epoch(Duration::from_secs(2)),
+            memory: 256 * 1024 * 1024,
+            stdout: 64 * 1024,
+            stderr: 16 * 1024,
+            timeout: Duration::from_secs(10),
+            snapshot: Duration::from_secs(10),
+            scratch: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RunStats {
+    /// Wall-clock time of the run, with the instantiation of the bot, or of the answer in a
+    /// session
+    pub wall_time: Duration,
+    /// Fuel consumed, when the CPU is limited with fuel
+    pub fuel: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BotExit {
+    /// The bot returned from `_start` or called `proc_exit`
+    Code(i32),
+    /// The bot was stopped by a wasm trap, a limit or a kill
+    Trap(String),
+    /// The bot used all the time it had
+    OutOfTime(Duration),
+}
+
+impl BotExit {
+    pub fn is_success(&self) -> bool {
+        matches!(self, BotExit::Code(0))
+    }
+}
+
+impl fmt::Display for BotExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotExit::Code(code) => write!(f, "exit code {}", code),
+            BotExit::Trap(message) => write!(f, "trap: {}", message),
+            BotExit::OutOfTime(time) => write!(f, "out of time after {} ms", time.as_millis()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BotRun {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit: BotExit,
+    pub stats: RunStats,
+}
+
+/// What a run does for its game, a game runs at most one run of each kind at once
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum RunKind {
+    /// A move, a session or a check of the upload
+    Bot,
+    /// The initialization of the runtime for its snapshot, in the background
+    Snapshot,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Cancel flag of the runs in progress of each game
+    running: HashMap<(usize, RunKind), Arc<AtomicBool>>,
+    /// Games whose bot was killed and must not be run again
+    killed: HashSet<usize>,
+    shutdown: bool,
+}
+
+/// Kill the run when dropped, so a cancelled move does not keep running in the background
+struct RunGuard {
+    registry: Arc<Mutex<Registry>>,
+    game_id: usize,
+    kind: RunKind,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Release);
+        let mut registry = self.registry.lock().unwrap();
+        let key = (self.game_id, self.kind);
+        if registry
+            .running
+            .get(&key)
+            .is_some_and(|cancel| Arc::ptr_eq(cancel, &self.cancel))
+        {
+            registry.running.remove(&key);
+        }
+    }
+}
+
+/// Wasm module to run with its arguments, environment and the directories it can read
+struct Program {
+    module_path: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    preopens: Vec<(PathBuf, String)>,
+    /// Directory kept between the runs of the bot, mapped read-write
+    scratch: Option<PathBuf>,
+    /// Seed of the random bytes of the run
+    seed: u64,
+}
+
+/// A bot started once for a whole game, reading one board per line on stdin and answering
+/// one move per line on stdout
+pub struct Session {
+    requests: mpsc::Sender<SessionRequest>,
+}
+
+/// Board sent to a session, the time the bot has to answer it and where to send its answer
+type SessionRequest = (String, Duration, oneshot::Sender<eyre::Result<BotRun>>);
+
+impl Session {
+    /// Send a board to the bot and wait for its move at most `timeout`
+    pub async fn play(&self, board: String, timeout: Duration) -> eyre::Result<BotRun> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send((board, timeout, reply))
+            .await
+            .map_err(|_| Error::msg("The bot session is closed"))?;
+        response.await?
+    }
+
+    /// Whether the bot stopped, because it exited, was killed or was idle for too long
+    pub fn is_closed(&self) -> bool {
+        self.requests.is_closed()
+    }
+}
+
+struct BotState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+/// Run bots inside the process with wasmtime, each run in its own store with its own limits
+#[derive(Clone)]
+pub struct Runner {
+    engine: Engine,
+    limits: RunnerLimits,
+    languages: Arc<Languages>,
+    registry: Arc<Mutex<Registry>>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Runner {
+    pub fn new(limits: RunnerLimits, languages: Languages) -> eyre::Result<Self> {
+        let mut config = Config::new();
+        // Runs are futures, so a run waiting in a host call can still be dropped
+        config.async_support(true);
+        // Epochs are also used to kill runs, even when the CPU is limited with fuel
+        config.epoch_interruption(true);
+        if let CpuLimit::Fuel(_) = limits.cpu {
+            config.consume_fuel(true);
+        }
+        let engine = Engine::new(&config).map_err(Error::msg)?;
+
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+
+        Ok(Self {
+            engine,
+            limits,
+            languages: Arc::new(languages),
+            registry: Arc::new(Mutex::new(Registry::default())),
+            cache: Arc::new(Mutex::new(Cache::default())),
+        })
+    }
+
+    pub fn languages(&self) -> &Languages {
+        &self.languages
+    }
+
+    /// Whether the moves of a bot are limited by its clock, they are limited by fuel otherwise.
+    /// Sessions are always limited by time
+    pub fn uses_clock(&self, session: bool) -> bool {
+        session || !matches!(self.limits.cpu, CpuLimit::Fuel(_))
+    }
+
+    /// Time a bot with `time_left` on its clock has to play one move, at most `limits.timeout`,
+    /// `None` when it is limited with fuel
+    pub fn move_time(&self, session: bool, time_left: Duration) -> Option<Duration> {
+        self.uses_clock(session)
+            .then(|| time_left.min(self.limits.timeout))
+    }
+
+    /// Wall-clock time of a run with `time` on the clock of its bot, at most `limits.timeout`
+    fn run_timeout(&self, time: Option<Duration>) -> Duration {
+        time.or(self.limits.cpu.timeout())
+            .map_or(self.limits.timeout, |time| time.min(self.limits.timeout))
+    }
+
+    /// Kill the runs of a game if they are running and refuse to run its bot again until
+    /// `release`
+    pub fn kill(&self, game_id: usize) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.killed.insert(game_id);
+        for ((running_id, kind), cancel) in registry.running.iter() {
+            if *running_id == game_id {
+                debug!("Kill the {:?} run of game {}", kind, game_id);
+                cancel.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    /// Whether the runs of a game were killed, because it was quit or the bot is shutting down
+    pub fn is_killed(&self, game_id: usize) -> bool {
+        let registry = self.registry.lock().unwrap();
+        registry.shutdown || registry.killed.contains(&game_id)
+    }
+
+    /// Forget a game once it is removed
+    pub fn release(&self, game_id: usize) {
+        self.registry.lock().unwrap().killed.remove(&game_id);
+    }
+
+    /// Kill every running bot and refuse to run new ones
+    pub fn kill_all(&self) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.shutdown = true;
+        for ((game_id, kind), cancel) in registry.running.iter() {
+            debug!("Kill the {:?} run of game {}", kind, game_id);
+            cancel.store(true, Ordering::Release);
+        }
+    }
+
+    /// Run the bot file of a game (a wasm module or a python script) with `stdin` as input for
+    /// at most `time`, the run is killed if the returned future is dropped
+    #[instrument(skip(self, stdin))]
+    pub async fn run(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        scratch: Option<&Path>,
+        seed: u64,
+        time: Option<Duration>,
+        stdin: String,
+    ) -> eyre::Result<BotRun> {
+        let mut program = self.program(file_path, self.language(file_path)?).await?;
+        program.scratch = scratch.map(Path::to_path_buf);
+        program.seed = seed;
+        self.run_program(game_id, program, time, stdin).await
+    }
+
+    /// Run a program once for a game
+    async fn run_program(
+        &self,
+        game_id: usize,
+        program: Program,
+        time: Option<Duration>,
+        stdin: String,
+    ) -> eyre::Result<BotRun> {
+        let guard = self.register(game_id, RunKind::Bot)?;
+        let stdout = MemoryOutputPipe::new(self.limits.stdout);
+        let stderr = MemoryOutputPipe::new(self.limits.stderr);
+        let (bot_stdout, bot_stderr) = (stdout.clone(), stderr.clone());
+        let result = self
+            .run_wasm(
+                &program,
+                |wasi| {
+                    wasi.stdin(MemoryInputPipe::new(stdin))
+                        .stdout(bot_stdout)
+                        .stderr(bot_stderr);
+                },
+                guard.cancel.clone(),
+                true,
+                Some(self.run_timeout(time)),
+            )
+            .await;
+        drop(guard);
+
+        let (exit, stats) = result?;
+        Ok(BotRun {
+            stdout: String::from_utf8_lossy(&stdout.contents()).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr.contents()).into_owned(),
+            exit,
+            stats,
+        })
+    }
+
+    /// Start the bot file of a game in a session, it is stopped when the session is dropped
+    #[instrument(skip(self))]
+    pub async fn start_session(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        scratch: Option<&Path>,
+        seed: u64,
+    ) -> eyre::Result<Session> {
+        let mut program = self.program(file_path, self.language(file_path)?).await?;
+        program.scratch = scratch.map(Path::to_path_buf);
+        program.seed = seed;
+        let guard = self.register(game_id, RunKind::Bot)?;
+        let cancel = guard.cancel.clone();
+        let (stdin, bot_stdin) = tokio::io::duplex(self.limits.stdout);
+        let (bot_stdout, stdout) = tokio::io::duplex(self.limits.stdout);
+        let stderr = MemoryOutputPipe::new(self.limits.stderr);
+        let bot_stderr = stderr.clone();
+        let stdout_limit = self.limits.stdout;
+        let runner = self.clone();
+        let bot = tokio::spawn(async move {
+            runner
+                .run_wasm(
+                    &program,
+                    |wasi| {
+                        wasi.stdin(AsyncStdinStream::new(AsyncReadStream::new(bot_stdin)))
+                            .stdout(AsyncStdoutStream::new(AsyncWriteStream::new(
+                                stdout_limit,
+                                bot_stdout,
+                            )))
+                            .stderr(bot_stderr);
+                    },
+                    cancel,
+                    // Sessions are only limited by time, each answer has its own timeout
+                    false,
+                    None,
+                )
+                .await
+        });
+
+        let (requests, receiver) = mpsc::channel(1);
+        tokio::spawn(run_session(
+            guard,
+            receiver,
+            stdin,
+            BufReader::new(stdout),
+            stderr,
+            bot,
+            self.limits,
+        ));
+        Ok(Session { requests })
+    }
+
+    /// Language of a bot file, from its extension
+    fn language(&self, file_path: &Path) -> eyre::Result<&Language> {
+        let extension = file_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or(Error::msg("Internal error with file extension"))?;
+        self.languages
+            .by_extension(extension)
+            .ok_or(Error::msg(format!("No runner for .{} files", extension)))
+    }
+
+    /// Module, arguments and directories running a bot file with `language`
+    async fn program(&self, file_path: &Path, language: &Language) -> eyre::Result<Program> {
+        let source = if language.args.iter().any(|arg| arg.contains("{source}")) {
+            tokio::fs::read_to_string(file_path).await?
+        } else {
+            String::new()
+        };
+        let file_name = file_path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_default();
+        let guest_file = format!("{}/{}", BOT_GUEST_DIR, file_name);
+        let args = language
+            .args
+            .iter()
+            .map(|arg| {
+                arg.replace("{file}", &guest_file)
+                    .replace("{source}", &source)
+            })
+            .collect();
+
+        let mut preopens = language
+            .dirs
+            .iter()
+            .map(|dir| (dir.host.clone(), dir.guest.clone()))
+            .collect::<Vec<_>>();
+        if let Some(bot_dir) = file_path.parent() {
+            preopens.push((bot_dir.to_path_buf(), BOT_GUEST_DIR.to_string()));
+        }
+
+        Ok(Program {
+            module_path: snapshot_path(file_path, language)
+                .or_else(|| language.runtime.clone())
+                .unwrap_or_else(|| file_path.to_path_buf()),
+            args,
+            env: language.env.clone().into_iter().collect(),
+            preopens,
+            scratch: None,
+            seed: 0,
+        })
+    }
+
+    fn register(&self, game_id: usize, kind: RunKind) -> eyre::Result<RunGuard> {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.shutdown || registry.killed.contains(&game_id) {
+            return Err(Error::msg(format!(
+                "The bot of game {} was killed",
+                game_id
+            )));
+        }
+        if registry.running.contains_key(&(game_id, kind)) {
+            return Err(Error::msg(format!(
+                "The bot of game {} is already running",
+                game_id
+            )));
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        registry.running.insert((game_id, kind), cancel.clone());
+        Ok(RunGuard {
+            registry: self.registry.clone(),
+            game_id,
+            kind,
+            cancel,
+        })
+    }
+
+    /// Run a program until it exits or runs for `timeout`, and with the fuel of a move if
+    /// `limit_fuel` is set
+    async fn run_wasm(
+        &self,
+        program: &Program,
+        stdio: impl FnOnce(&mut WasiCtxBuilder),
+        cancel: Arc<AtomicBool>,
+        limit_fuel: bool,
+        timeout: Option<Duration>,
+    ) -> eyre::Result<(BotExit, RunStats)> {
+        let module = self.module(&program.module_path).await?;
+        let scratch = match &program.scratch {
+            Some(scratch) => Some(self.watch_scratch(scratch, cancel.clone())?),
+            None => None,
+        };
+        let (mut store, mut linker) =
+            self.wasi_store(program, stdio, cancel.clone(), limit_fuel, timeout)?;
+        if let Some(scratch) = &scratch {
+            scratch.limit_writes(&mut linker, &mut store)?;
+        }
+
+        let start = Instant::now();
+        let result = watchdog(
+            async {
+                let instance = linker.instantiate_async(&mut store, &module).await?;
+                instance
+                    .get_typed_func::<(), ()>(&mut store, "_start")?
+                    .call_async(&mut store, ())
+                    .await
+            },
+            &cancel,
+            timeout,
+        )
+        .await;
+        let wall_time = start.elapsed();
+
+        let mut exit = bot_exit(result);
+        if scratch.is_some_and(ScratchWatch::finish) {
+            exit = BotExit::Trap(format!(
+                "Scratch above its quota of {} bytes, it was wiped",
+                self.limits.scratch
+            ));
+        }
+        let fuel = self.fuel_used(&store, limit_fuel)?;
+        Ok((exit, RunStats { wall_time, fuel }))
+    }
+
+    /// Store with the WASI context and the limits of a run, and a linker with WASI
+    fn wasi_store(
+        &self,
+        program: &Program,
+        stdio: impl FnOnce(&mut WasiCtxBuilder),
+        cancel: Arc<AtomicBool>,
+        limit_fuel: bool,
+        timeout: Option<Duration>,
+    ) -> eyre::Result<(Store<BotState>, Linker<BotState>)> {
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.args(&program.args).envs(&program.env);
+        make_deterministic(&mut wasi, program.seed);
+        stdio(&mut wasi);
+        for (host_path, guest_path) in program.preopens.iter() {
+            wasi.preopened_dir(host_path, guest_path, DirPerms::READ, FilePerms::READ)
+                .map_err(Error::msg)?;
+        }
+        if let Some(scratch) = &program.scratch {
+            wasi.preopened_dir(
+                scratch,
+                SCRATCH_GUEST_DIR,
+                DirPerms::all(),
+                FilePerms::all(),
+            )
+            .map_err(Error::msg)?;
+        }
+
+        let mut store = Store::new(
+            &self.engine,
+            BotState {
+                wasi: wasi.build_p1(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.limits.memory)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        self.limit_store(&mut store, cancel, limit_fuel, timeout)?;
+
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::preview1::add_to_linker_async(&mut linker, |state: &mut BotState| {
+            &mut state.wasi
+        })
+        .map_err(Error::msg)?;
+        Ok((store, linker))
+    }
+
+    /// Apply the fuel limit of a run, its wall-clock `timeout` starting now and its kill switch
+    /// to a store
+    fn limit_store<T>(
+        &self,
+        store: &mut Store<T>,
+        cancel: Arc<AtomicBool>,
+        limit_fuel: bool,
+        timeout: Option<Duration>,
+    ) -> eyre::Result<()> {
+        match self.limits.cpu {
+            CpuLimit::Fuel(fuel) if limit_fuel => store.set_fuel(fuel).map_err(Error::msg)?,
+            CpuLimit::Fuel(_) => store.set_fuel(u64::MAX).map_err(Error::msg)?,
+            CpuLimit::Epoch(_) => (),
+        }
+        // The run yields at each tick, for `watchdog` to notice a kill or a timeout while wasm
+        // code runs
+        let start = Instant::now();
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            if cancel.load(Ordering::Acquire) {
+                return Err(wasmtime::Error::msg("Bot was killed"));
+            }
+            if let Some(timeout) = timeout {
+                if start.elapsed() > timeout {
+                    return Err(wasmtime::Error::new(OutOfTime(timeout)));
+                }
+            }
+            Ok(UpdateDeadline::Yield(1))
+        });
+        Ok(())
+    }
+
+    /// Fuel consumed in a store limited with `limit_store`
+    fn fuel_used<T>(&self, store: &Store<T>, limit_fuel: bool) -> eyre::Result<Option<u64>> {
+        Ok(match self.limits.cpu {
+            CpuLimit::Fuel(fuel) if limit_fuel => {
+                Some(fuel - store.get_fuel().map_err(Error::msg)?)
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Error stopping a run that used all the time it had
+#[derive(Debug)]
+struct OutOfTime(Duration);
+
+impl fmt::Display for OutOfTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bot ran for more than {} ms", self.0.as_millis())
+    }
+}
+
+impl std::error::Error for OutOfTime {}
+
+/// Wait for a run until it returns, is killed or runs for `timeout`, even while it waits in a
+/// host call where the epoch callback is not called
+async fn watchdog<T>(
+    run: impl Future<Output = wasmtime::Result<T>>,
+    cancel: &AtomicBool,
+    timeout: Option<Duration>,
+) -> wasmtime::Result<T> {
+    let killed = async {
+        while !cancel.load(Ordering::Acquire) {
+            tokio::time::sleep(EPOCH_TICK).await;
+        }
+    };
+    let run = async {
+        tokio::select! {
+            result = run => result,
+            () = killed => Err(wasmtime::Error::msg("Bot was killed")),
+        }
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, run)
+            .await
+            .unwrap_or_else(|_| Err(wasmtime::Error::new(OutOfTime(timeout)))),
+        None => run.await,
+    }
+}
+
+/// Exiting with `proc_exit` is also reported as an error
+fn bot_exit(result: wasmtime::Result<()>) -> BotExit {
+    match result {
+        Ok(()) => BotExit::Code(0),
+        Err(error) => {
+            if let Some(OutOfTime(budget)) = error.downcast_ref::<OutOfTime>() {
+                return BotExit::OutOfTime(*budget);
+            }
+            match error.downcast_ref::<I32Exit>() {
+                Some(I32Exit(code)) => BotExit::Code(*code),
+                None => BotExit::Trap(error.root_cause().to_string()),
+            }
+        }
+    }
+}
+
+/// Forward each board to the bot of a session and answer with its move, until the session is
+/// dropped, goes idle or the bot stops
+async fn run_session(
+    guard: RunGuard,
+    mut requests: mpsc::Receiver<SessionRequest>,
+    mut stdin: DuplexStream,
+    mut stdout: BufReader<DuplexStream>,
+    stderr: MemoryOutputPipe,
+    bot: JoinHandle<eyre::Result<(BotExit, RunStats)>>,
+    limits: RunnerLimits,
+) {
+    while let Ok(Some((board, timeout, reply))) =
+        tokio::time::timeout(SESSION_IDLE_TIMEOUT, requests.recv()).await
+    {
+        let start = Instant::now();
+        // The stderr of the previous turns was already reported
+        let stderr_start = stderr.contents().len();
+        let mut line = String::new();
+        let answer = tokio::time::timeout(timeout, async {
+            stdin.write_all(format!("{}\n", board).as_bytes()).await?;
+            (&mut stdout)
+                .take(limits.stdout as u64)
+                .read_line(&mut line)
+                .await
+        })
+        .await;
+        let stats = RunStats {
+            wall_time: start.elapsed(),
+            fuel: None,
+        };
+
+        let timeout = match answer {
+            Ok(Ok(length)) if length > 0 => {
+                read_available_lines(&mut stdout, &mut line, limits.stdout).await;
+                let _ = reply.send(Ok(BotRun {
+                    stdout: line,
+                    stderr: String::from_utf8_lossy(&stderr.contents()[stderr_start..])
+                        .into_owned(),
+                    exit: BotExit::Code(0),
+                    stats,
+                }));
+                continue;
+            }
+            // The bot closed its stdin or stdout, it is exiting
+            Ok(_) => None,
+            Err(_) => Some(BotExit::OutOfTime(timeout)),
+        };
+
+        // The session is over, stop the bot and report how it ended
+        guard.cancel.store(true, Ordering::Release);
+        drop(stdin);
+        drop(stdout);
+        let result = match bot.await {
+            Ok(Ok((bot_exit, _))) => Ok(BotRun {
+                stdout: line,
+                stderr: String::from_utf8_lossy(&stderr.contents()[stderr_start..]).into_owned(),
+                exit: timeout.unwrap_or(bot_exit),
+                stats,
+            }),
+            Ok(Err(error)) => Err(error),
+            Err(error) => Err(error.into()),
+        };
+        let _ = reply.send(result);
+        break;
+    }
+    debug!("Session of game {} is over", guard.game_id);
+    drop(guard);
+    drop(requests);
+}
+
+/// Append the lines a bot printed right after its answer, until no complete line comes within
+/// `SESSION_LINE_GRACE` or `limit` bytes are read
+async fn read_available_lines(
+    stdout: &mut BufReader<DuplexStream>,
+    output: &mut String,
+    limit: usize,
+) {
+    while output.len() < limit {
+        // Unlike `read_line`, `fill_buf` keeps what it read when it times out
+        match tokio::time::timeout(SESSION_LINE_GRACE, stdout.fill_buf()).await {
+            Ok(Ok(buffer)) if buffer.contains(&b'\n') => (),
+            _ => return,
+        }
+        let read = (&mut *stdout)
+            .take((limit - output.len()) as u64)
+            .read_line(output)
+            .await;
+        if !read.is_ok_and(|length| length > 0) {
+            return;
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::runner::Runner;
+use eyre::Error;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+use tracing::instrument;
+use tracing::log::{debug, warn};
+use wasmtime::component::Component;
+use wasmtime::Module;
+
+/// Directory of the compiled wasm files, kept between restarts for the language runtimes
+pub const CACHE_DIR: &str = "./cache";
+const CACHE_EXTENSION: &str = "cwasm";
+
+/// Number of the temporary files written before being renamed in the cache
+static TMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Compiled code of a wasm file
+#[derive(Clone)]
+pub enum Compiled {
+    Module(Module),
+    Component(Component),
+}
+
+struct CacheEntry {
+    /// Modification time of the wasm file when it was compiled, to notice when it changes
+    modified: SystemTime,
+    /// Name of the compiled file in `CACHE_DIR`, from the content of the wasm file and the
+    /// wasmtime version and settings
+    key: String,
+    compiled: Compiled,
+}
+
+/// Compiled wasm files by path, shared by the runs of every game
+#[derive(Default)]
+pub struct Cache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Runtimes exporting their globals and memories for their snapshots, by path
+    pub instrumented: HashMap<PathBuf, Module>,
+}
+
+impl Cache {
+    fn is_used(&self, key: &str) -> bool {
+        self.entries.values().any(|entry| entry.key == key)
+    }
+}
+
+fn cache_file(key: &str) -> PathBuf {
+    Path::new(CACHE_DIR).join(format!("{}.{}", key, CACHE_EXTENSION))
+}
+
+impl Runner {
+    /// Compile the runtimes of the languages and remove the compiled files of older bots
+    #[instrument(skip(self))]
+    pub fn prepare_cache(&self) -> eyre::Result<()> {
+        std::fs::create_dir_all(CACHE_DIR)?;
+        for language in self.languages.iter() {
+            if let Some(runtime) = &language.runtime {
+                if let Err(error) = self.compiled(runtime) {
+                    warn!("Cannot compile the runtime of {}: {}", language.name, error);
+                }
+            }
+        }
+        for file in std::fs::read_dir(CACHE_DIR)? {
+            let path = file?.path();
+            let Some(key) = path.file_stem().and_then(|key| key.to_str()) else {
+                continue;
+            };
+            let cache = self.cache.lock().unwrap();
+            if !cache.is_used(key) {
+                debug!("Remove the unused compiled file {}", path.display());
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a bot file, or the runtime of its language, before the first move of its game
+    #[instrument(skip(self))]
+    pub async fn precompile(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        seed: u64,
+    ) -> eyre::Result<()> {
+        let language = self.language(file_path)?;
+        let module_path = language
+            .runtime
+            .clone()
+            .unwrap_or_else(|| file_path.to_path_buf());
+        let runner = self.clone();
+        tokio::task::spawn_blocking(move || runner.compiled(&module_path)).await??;
+        self.start_snapshot(game_id, file_path, language, seed)
+            .await
+    }
+
+    /// Forget the compiled code of the files of a game folder once the game is removed, a
+    /// compiled file is removed when no other file has the same content
+    #[instrument(skip(self))]
+    pub fn forget(&self, directory: &Path) -> eyre::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        let paths = cache
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(directory))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for path in paths {
+            if let Some(entry) = cache.entries.remove(&path) {
+                if !cache.is_used(&entry.key) {
+                    std::fs::remove_file(cache_file(&entry.key))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiled module of a wasm file, compiled on a blocking thread if needed
+    pub async fn module(&self, path: &Path) -> eyre::Result<Module> {
+        match self.compiled_async(path).await? {
+            Compiled::Module(module) => Ok(module),
+            Compiled::Component(_) => Err(Error::msg(format!(
+                "{} is a component, not a module",
+                path.display()
+            ))),
+        }
+    }
+
+    pub async fn component(&self, path: &Path) -> eyre::Result<Component> {
+        match self.compiled_async(path).await? {
+            Compiled::Component(component) => Ok(component),
+            Compiled::Module(_) => Err(Error::msg(format!(
+                "{} is a module, not a component",
+                path.display()
+            ))),
+        }
+    }
+
+    async fn compiled_async(&self, path: &Path) -> eyre::Result<Compiled> {
+        let runner = self.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || runner.compiled(&path)).await?
+    }
+
+    /// Compiled code of a wasm file, from the memory, else from `CACHE_DIR`, else compiled now
+    fn compiled(&self, path: &Path) -> eyre::Result<Compiled> {
+        let modified = std::fs::metadata(path)?.modified()?;
+        if let Some(entry) = self.cache.lock().unwrap().entries.get(path) {
+            if entry.modified == modified {
+                return Ok(entry.compiled.clone());
+            }
+        }
+
+        let bytes = std::fs::read(path)?;
+        let key = self.cache_key(&bytes);
+        let compiled = self.load_or_compile(&bytes, &key)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        // The folder of a game can be removed and forgotten while one of its files compiles
+        if !path.exists() {
+            if !cache.is_used(&key) {
+                let _ = std::fs::remove_file(cache_file(&key));
+            }
+            return Err(Error::msg(format!("{} was removed", path.display())));
+        }
+        let previous = cache.entries.insert(
+            path.to_path_buf(),
+            CacheEntry {
+                modified,
+                key,
+                compiled: compiled.clone(),
+            },
+        );
+        // The file changed since it was compiled
+        if let Some(previous) = previous {
+            if !cache.is_used(&previous.key) {
+                let _ = std::fs::remove_file(cache_file(&previous.key));
+            }
+        }
+        Ok(compiled)
+    }
+
+    /// Hash of the content of a wasm file and of the wasmtime version and settings, which
+    /// must be the same to load a compiled file
+    fn cache_key(&self, bytes: &[u8]) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.engine
+            .precompile_compatibility_hash()
+            .hash(&mut hasher);
+        let content = Sha256::digest(bytes)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        format!("{}-{:016x}", content, hasher.finish())
+    }
+
+    #[instrument(skip(self, bytes))]
+    fn load_or_compile(&self, bytes: &[u8], key: &str) -> eyre::Result<Compiled> {
+        let is_component = wasmparser::Parser::is_component(bytes);
+        let path = cache_file(key);
+        if path.exists() {
+            match self.load(&path, is_component) {
+                Ok(compiled) => return Ok(compiled),
+                Err(error) => warn!("Compile {} again: {}", path.display(), error),
+            }
+        }
+
+        debug!("Compile {}", path.display());
+        let compiled = if is_component {
+            self.engine.precompile_component(bytes)
+        } else {
+            self.engine.precompile_module(bytes)
+        }
+        .map_err(Error::msg)?;
+        // Written aside then renamed, so a file of the cache is always complete
+        let tmp_path = Path::new(CACHE_DIR).join(format!(
+            "{}.{}.tmp",
+            key,
+            TMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(CACHE_DIR)?;
+        std::fs::write(&tmp_path, compiled)?;
+        std::fs::rename(&tmp_path, &path)?;
+        self.load(&path, is_component)
+    }
+
+    fn load(&self, path: &Path, is_component: bool) -> eyre::Result<Compiled> {
+        // SAFETY: the files of `CACHE_DIR` are only written by `load_or_compile`, from code
+        // compiled by wasmtime, and wasmtime refuses files compiled by another version
+        let compiled = unsafe {
+            if is_component {
+                Compiled::Component(
+                    Component::deserialize_file(&self.engine, path).map_err(Error::msg)?,
+                )
+            } else {
+                Compiled::Module(Module::deserialize_file(&self.engine, path).map_err(Error::msg)?)
+            }
+        };
+        Ok(compiled)
+    }
+}
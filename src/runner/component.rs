@@ -0,0 +1,191 @@
+use crate::protocol::{self, State};
+use crate::runner::determinism::make_deterministic;
+use crate::runner::{bot_exit, watchdog, BotRun, RunKind, RunStats, Runner};
+use eyre::Error;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::instrument;
+use wasmtime::component::{Linker, ResourceTable};
+use wasmtime::{Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::{IoView, WasiCtx, WasiCtxBuilder, WasiView};
+
+wasmtime::component::bindgen!({
+    path: "wit/minesweeper-bot.wit",
+    world: "minesweeper-bot",
+    async: true,
+});
+
+struct ComponentState {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+    /// Messages of the `log` import, kept up to `max_logs` bytes
+    logs: String,
+    max_logs: usize,
+}
+
+impl IoView for ComponentState {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+
+impl WasiView for ComponentState {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+impl MinesweeperBotImports for ComponentState {
+    async fn log(&mut self, message: String) {
+        if self.logs.len() + message.len() < self.max_logs {
+            self.logs.push_str(&message);
+            self.logs.push('\n');
+        }
+    }
+}
+
+impl From<&State> for Board {
+    fn from(state: &State) -> Self {
+        Self {
+            width: state.width as u32,
+            height: state.height as u32,
+            cells: state.cells.clone(),
+            flagged: state
+                .flags
+                .iter()
+                .map(|&(x, y)| Position {
+                    x: x as u32,
+                    y: y as u32,
+                })
+                .collect(),
+            mines_remaining: state.mines_remaining,
+            move_number: state.move_number as u32,
+            time_left_ms: state.time_left_ms.map(|time_left| time_left as u64),
+        }
+    }
+}
+
+impl From<Action> for protocol::Action {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Reveal(Position { x, y }) => protocol::Action::Reveal {
+                x: x as usize,
+                y: y as usize,
+            },
+            Action::Flag(Position { x, y }) => protocol::Action::Flag {
+                x: x as usize,
+                y: y as usize,
+            },
+            Action::Unflag(Position { x, y }) => protocol::Action::Unflag {
+                x: x as usize,
+                y: y as usize,
+            },
+            Action::Chord(Position { x, y }) => protocol::Action::Chord {
+                x: x as usize,
+                y: y as usize,
+            },
+        }
+    }
+}
+
+/// Whether a bot file is a component instead of a core WASI module
+#[instrument]
+pub async fn is_component(file_path: &Path) -> eyre::Result<bool> {
+    if file_path
+        .extension()
+        .is_none_or(|extension| !extension.eq_ignore_ascii_case("wasm"))
+    {
+        return Ok(false);
+    }
+    let bytes = tokio::fs::read(file_path).await?;
+    Ok(wasmparser::Parser::is_component(&bytes))
+}
+
+impl Runner {
+    /// Instantiate the component of a game and call its `next-move` export, the run is killed
+    /// if the returned future is dropped
+    #[instrument(skip(self, state))]
+    pub async fn run_component(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        seed: u64,
+        time: Option<Duration>,
+        state: State,
+    ) -> eyre::Result<(BotRun, Option<protocol::Action>)> {
+        let guard = self.register(game_id, RunKind::Bot)?;
+        let result = self
+            .call_component(file_path, seed, time, &state, guard.cancel.clone())
+            .await;
+        drop(guard);
+        result
+    }
+
+    async fn call_component(
+        &self,
+        file_path: &Path,
+        seed: u64,
+        time: Option<Duration>,
+        state: &State,
+        cancel: Arc<AtomicBool>,
+    ) -> eyre::Result<(BotRun, Option<protocol::Action>)> {
+        let component = self.component(file_path).await?;
+        let mut wasi = WasiCtxBuilder::new();
+        make_deterministic(&mut wasi, seed);
+
+        let mut store = Store::new(
+            &self.engine,
+            ComponentState {
+                wasi: wasi.build(),
+                table: ResourceTable::new(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.limits.memory)
+                    .build(),
+                logs: String::new(),
+                max_logs: self.limits.stderr,
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        let timeout = Some(self.run_timeout(time));
+        self.limit_store(&mut store, cancel.clone(), true, timeout)?;
+
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::add_to_linker_async(&mut linker).map_err(Error::msg)?;
+        MinesweeperBot::add_to_linker(&mut linker, |state: &mut ComponentState| state)
+            .map_err(Error::msg)?;
+
+        let start = Instant::now();
+        let result = watchdog(
+            async {
+                let bot =
+                    MinesweeperBot::instantiate_async(&mut store, &component, &linker).await?;
+                bot.call_next_move(&mut store, &Board::from(state)).await
+            },
+            &cancel,
+            timeout,
+        )
+        .await;
+        let wall_time = start.elapsed();
+
+        let (action, exit) = match result {
+            Ok(action) => (Some(action), bot_exit(Ok(()))),
+            Err(error) => (None, bot_exit(Err(error))),
+        };
+        let fuel = self.fuel_used(&store, true)?;
+        Ok((
+            BotRun {
+                stdout: action
+                    .as_ref()
+                    .map(|action| format!("{:?}", action))
+                    .unwrap_or_default(),
+                stderr: std::mem::take(&mut store.data_mut().logs),
+                exit,
+                stats: RunStats { wall_time, fuel },
+            },
+            action.map(protocol::Action::from),
+        ))
+    }
+}
@@ -0,0 +1,111 @@
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, WasiCtxBuilder};
+
+/// Wall clock time when a run starts, the same for every run
+const START_TIME: Duration = Duration::from_secs(1_700_000_000);
+/// Largest seed, seeds can be given back as a Discord integer option
+pub const MAX_SEED: u64 = (1 << 53) - 1;
+/// ChaCha stream of the board generator, the runs use the default stream 0
+const BOARD_STREAM: u64 = 1;
+/// ChaCha stream of the penalty generator
+const PENALTY_STREAM: u64 = 2;
+/// ChaCha stream of the insecure random bytes of a run
+const INSECURE_STREAM: u64 = 3;
+/// ChaCha stream of the insecure random seed of a run, which the bot reads as is
+const INSECURE_SEED_STREAM: u64 = 4;
+/// Time the clocks of a run advance each time they are read
+const CLOCK_STEP: Duration = Duration::from_millis(1);
+
+/// Clock starting at `START_TIME` and advancing by `CLOCK_STEP` on each read, so a bot
+/// waiting for some time still sees the time pass
+#[derive(Default)]
+pub struct DeterministicClock {
+    reads: AtomicU64,
+}
+
+impl DeterministicClock {
+    fn elapsed(&self) -> Duration {
+        let reads = self.reads.fetch_add(1, Ordering::Relaxed);
+        Duration::from_nanos((CLOCK_STEP.as_nanos() as u64).saturating_mul(reads))
+    }
+}
+
+impl HostWallClock for DeterministicClock {
+    fn resolution(&self) -> Duration {
+        CLOCK_STEP
+    }
+
+    fn now(&self) -> Duration {
+        START_TIME + self.elapsed()
+    }
+}
+
+impl HostMonotonicClock for DeterministicClock {
+    fn resolution(&self) -> u64 {
+        CLOCK_STEP.as_nanos() as u64
+    }
+
+    fn now(&self) -> u64 {
+        self.elapsed().as_nanos() as u64
+    }
+}
+
+/// Seed of the run of one move of a game, different for each move
+pub fn turn_seed(game_seed: u64, turn: usize) -> u64 {
+    game_seed ^ (turn as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// Generator seeded with `seed` on the ChaCha stream `stream`
+fn stream_rng(seed: u64, stream: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+/// Generator of the board of a game, on its own stream so the random bytes given to the bot
+/// do not reveal it
+pub fn board_rng(game_seed: u64) -> ChaCha8Rng {
+    stream_rng(game_seed, BOARD_STREAM)
+}
+
+/// Generator of the cells revealed by the penalty of a failed turn, from the seed of the turn
+pub fn penalty_rng(turn_seed: u64) -> ChaCha8Rng {
+    stream_rng(turn_seed, PENALTY_STREAM)
+}
+
+/// Insecure random seed of a run, drawn from its own stream since the bot can read it and
+/// the seed of the run would give away the seed of the game
+fn insecure_seed(seed: u64) -> u128 {
+    let mut rng = stream_rng(seed, INSECURE_SEED_STREAM);
+    (rng.next_u64() as u128) << 64 | rng.next_u64() as u128
+}
+
+/// Make a run depend only on its input: random bytes generated from `seed` and clocks
+/// starting at a fixed time
+pub fn make_deterministic(wasi: &mut WasiCtxBuilder, seed: u64) {
+    wasi.secure_random(ChaCha8Rng::seed_from_u64(seed))
+        .insecure_random(stream_rng(seed, INSECURE_STREAM))
+        .insecure_random_seed(insecure_seed(seed))
+        .wall_clock(DeterministicClock::default())
+        .monotonic_clock(DeterministicClock::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insecure_seed_hides_turn_seed() {
+        for turn in 0..4 {
+            let seed = turn_seed(12345, turn);
+            let insecure = insecure_seed(seed);
+            assert_ne!(insecure, seed as u128);
+            assert_ne!(insecure as u64, seed);
+            assert_ne!((insecure >> 64) as u64, seed);
+            assert_eq!(insecure, insecure_seed(seed));
+        }
+    }
+}
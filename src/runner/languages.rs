@@ -0,0 +1,137 @@
+use eyre::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+use tracing::log::warn;
+
+/// Directory of the guest where the folder of the uploaded files is mapped read-only
+pub const BOT_GUEST_DIR: &str = "/bot";
+
+/// How to run the files of one language, read from `runners.toml`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Language {
+    pub name: String,
+    /// MIME types of the attachments, without parameters like `charset`
+    pub content_types: Vec<String>,
+    /// Extensions of the attachments, the first one is used to store them
+    pub extensions: Vec<String>,
+    /// Wasm module running the uploaded file, the uploaded file is the module when missing
+    pub runtime: Option<PathBuf>,
+    /// Arguments given to the module, `{source}` is replaced by the content of the uploaded
+    /// file and `{file}` by its path in the guest
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Arguments given to the runtime to check the syntax of an uploaded file read on stdin,
+    /// no check when empty
+    #[serde(default)]
+    pub check: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Host directories mapped read-only in the guest
+    #[serde(default)]
+    pub dirs: Vec<MappedDir>,
+    /// Boot the runtime and load the uploaded file once per game, each move resuming from
+    /// this snapshot
+    pub snapshot: Option<Snapshot>,
+}
+
+/// How to initialize a runtime before its snapshot, following the Wizer convention: the
+/// runtime exports an initialization function, and its `_start` resumes from its state
+#[derive(Debug, Clone, Deserialize)]
+pub struct Snapshot {
+    /// Exported function booting the runtime and loading the uploaded file
+    #[serde(default = "default_snapshot_init")]
+    pub init: String,
+    /// Arguments given to the runtime during the initialization, like `args`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_snapshot_init() -> String {
+    "wizer.initialize".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MappedDir {
+    pub host: PathBuf,
+    pub guest: String,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    language: Vec<Language>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Languages(Vec<Language>);
+
+impl Languages {
+    /// Read the registry, skipping the languages whose runtime is not installed
+    #[instrument]
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| Error::msg(format!("Cannot read {}: {}", path.display(), error)))?;
+        let config: Config = toml::from_str(&content)?;
+        let languages = config
+            .language
+            .into_iter()
+            .filter(|language| match &language.runtime {
+                Some(runtime) if !runtime.exists() => {
+                    warn!(
+                        "Language {} disabled, {} is missing",
+                        language.name,
+                        runtime.display()
+                    );
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+        Ok(Self(languages))
+    }
+
+    /// Language of a script, found with its file name or else its content type
+    pub fn find(&self, content_type: Option<&str>, filename: &str) -> Option<&Language> {
+        let content_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(str::trim);
+        Path::new(filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.by_extension(extension))
+            .or_else(|| {
+                self.0.iter().find(|language| {
+                    content_type.is_some_and(|content_type| {
+                        language
+                            .content_types
+                            .iter()
+                            .any(|ty| ty.eq_ignore_ascii_case(content_type))
+                    })
+                })
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Language> {
+        self.0.iter()
+    }
+
+    /// Language of an extension, whatever its case
+    pub fn by_extension(&self, extension: &str) -> Option<&Language> {
+        self.0.iter().find(|language| {
+            language
+                .extensions
+                .iter()
+                .any(|ext| ext.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Names and extensions of the languages, to tell users what they can upload
+    pub fn describe(&self) -> String {
+        self.0
+            .iter()
+            .map(|language| format!("{} (.{})", language.name, language.extensions.join(", .")))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,332 @@
+use crate::runner::Runner;
+use eyre::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::instrument;
+use tracing::log::{debug, warn};
+use wasmtime::{Caller, Extern, Instance, Linker, Module, Store, WasmParams, WasmResults};
+
+/// Folder of the scratch directories, one per owner and bot name, kept between restarts
+pub const SCRATCH_DIR: &str = "./scratch";
+/// Directory of the guest where the scratch of a bot is mapped read-write
+pub const SCRATCH_GUEST_DIR: &str = "/scratch";
+/// Interval between two checks of the size of a scratch during a run
+const SCRATCH_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+/// Module of the WASI functions of core modules
+const PREVIEW1: &str = "wasi_snapshot_preview1";
+/// Module calling the WASI writes for `limit_writes`, with the memory of the bot
+const FORWARD_MODULE: &str = r#"(module
+    (import "bot" "memory" (memory 0))
+    (import "wasi" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi" "fd_pwrite" (func $fd_pwrite (param i32 i32 i32 i64 i32) (result i32)))
+    (export "memory" (memory 0))
+    (func (export "fd_write") (param i32 i32 i32 i32) (result i32)
+        (call $fd_write (local.get 0) (local.get 1) (local.get 2) (local.get 3)))
+    (func (export "fd_pwrite") (param i32 i32 i32 i64 i32) (result i32)
+        (call $fd_pwrite (local.get 0) (local.get 1) (local.get 2) (local.get 3) (local.get 4))))"#;
+/// Size counted for each file and directory, so the quota also limits their number
+const MIN_ENTRY_SIZE: u64 = 4096;
+
+/// Scratch directory of a bot, from its owner and its name without extension. Concurrent games
+/// of the same bot share it, nothing locks it
+pub fn scratch_path(owner: u64, bot_name: &str) -> PathBuf {
+    let bot_name = Path::new(bot_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(64)
+        .collect::<String>();
+    Path::new(SCRATCH_DIR)
+        .join(owner.to_string())
+        .join(if bot_name.is_empty() {
+            "bot"
+        } else {
+            &bot_name
+        })
+}
+
+/// Size used by a scratch directory, each entry counting for at least `MIN_ENTRY_SIZE`
+pub fn scratch_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            MIN_ENTRY_SIZE + scratch_size(&entry.path())?
+        } else {
+            metadata.len().max(MIN_ENTRY_SIZE)
+        };
+    }
+    Ok(size)
+}
+
+/// Remove the content of a scratch directory
+#[instrument]
+pub fn wipe_scratch(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
+    std::fs::create_dir_all(path)
+}
+
+/// Checks the size of a scratch during a run and kills the run above the quota, it stops when
+/// dropped. The writes of WASI modules are also checked before they are done, see
+/// `limit_writes`, the periodic check catching the entries and the sparse files
+pub struct ScratchWatch {
+    state: Arc<WatchState>,
+}
+
+struct WatchState {
+    path: PathBuf,
+    quota: u64,
+    /// Size of the scratch counting the writes of the run, above the real size when a write
+    /// overwrites a file
+    used: Mutex<u64>,
+    done: AtomicBool,
+    exceeded: AtomicBool,
+    cancel: Arc<AtomicBool>,
+}
+
+impl WatchState {
+    /// Whether `length` more bytes fit in the quota, the run is killed otherwise
+    fn reserve(&self, length: u64) -> bool {
+        let mut used = self.used.lock().unwrap();
+        if used.saturating_add(length) > self.quota {
+            // Overwritten or removed files were counted, the real size can be lower
+            *used = scratch_size(&self.path).unwrap_or(u64::MAX);
+        }
+        if used.saturating_add(length) > self.quota {
+            debug!(
+                "Write of {} bytes above the quota of {}",
+                length,
+                self.path.display()
+            );
+            self.exceed();
+            return false;
+        }
+        true
+    }
+
+    fn exceed(&self) {
+        self.exceeded.store(true, Ordering::Release);
+        self.cancel.store(true, Ordering::Release);
+    }
+}
+
+impl ScratchWatch {
+    /// Stop watching once the run is over, and wipe the scratch if it went above its quota
+    pub fn finish(self) -> bool {
+        let state = &self.state;
+        state.done.store(true, Ordering::Release);
+        // The bot may have written since the last check
+        let exceeded = state.exceeded.load(Ordering::Acquire)
+            || scratch_size(&state.path).is_ok_and(|size| size > state.quota);
+        if exceeded {
+            if let Err(error) = wipe_scratch(&state.path) {
+                warn!("Cannot wipe {}: {}", state.path.display(), error);
+            }
+        }
+        exceeded
+    }
+
+    /// Check `fd_write` and `fd_pwrite` against the quota before they run, so a single write
+    /// cannot go past it. Only the scratch is writable, the writes to files (not to stdio)
+    /// are the writes to the scratch
+    pub fn limit_writes<T: Send + 'static>(
+        &self,
+        linker: &mut Linker<T>,
+        store: &mut Store<T>,
+    ) -> eyre::Result<()> {
+        let mut imports = Vec::new();
+        for name in ["fd_write", "fd_pwrite"] {
+            imports.push(
+                linker
+                    .get(&mut *store, PREVIEW1, name)
+                    .ok_or(Error::msg(format!("{} is missing from the linker", name)))?,
+            );
+        }
+        let forward = Arc::new(Forward {
+            module: Module::new(store.engine(), FORWARD_MODULE).map_err(Error::msg)?,
+            imports,
+            instance: Mutex::new(None),
+        });
+        linker.allow_shadowing(true);
+
+        let (state, forwarded) = (self.state.clone(), forward.clone());
+        linker
+            .func_wrap_async(
+                PREVIEW1,
+                "fd_write",
+                move |mut caller: Caller<'_, T>,
+                      (fd, iovs, iovs_len, written): (i32, i32, i32, i32)| {
+                    let (state, forward) = (state.clone(), forwarded.clone());
+                    Box::new(async move {
+                        let length = iovs_length(&mut caller, iovs, iovs_len);
+                        if fd > 2 && !state.reserve(length) {
+                            return Err(wasmtime::Error::msg("Scratch above its quota"));
+                        }
+                        let errno: i32 = forward
+                            .call(&mut caller, "fd_write", (fd, iovs, iovs_len, written))
+                            .await?;
+                        if fd > 2 {
+                            count_written(&mut caller, &state, errno, written);
+                        }
+                        Ok(errno)
+                    })
+                },
+            )
+            .map_err(Error::msg)?;
+
+        let (state, forwarded) = (self.state.clone(), forward);
+        linker
+            .func_wrap_async(
+                PREVIEW1,
+                "fd_pwrite",
+                move |mut caller: Caller<'_, T>,
+                      (fd, iovs, iovs_len, offset, written): (i32, i32, i32, i64, i32)| {
+                    let (state, forward) = (state.clone(), forwarded.clone());
+                    Box::new(async move {
+                        let length = iovs_length(&mut caller, iovs, iovs_len);
+                        // A file cannot end after the quota, even with a hole before the data
+                        let end = (offset as u64).saturating_add(length);
+                        if fd > 2 && (end > state.quota || !state.reserve(length)) {
+                            state.exceed();
+                            return Err(wasmtime::Error::msg("Scratch above its quota"));
+                        }
+                        let errno: i32 = forward
+                            .call(
+                                &mut caller,
+                                "fd_pwrite",
+                                (fd, iovs, iovs_len, offset, written),
+                            )
+                            .await?;
+                        if fd > 2 {
+                            count_written(&mut caller, &state, errno, written);
+                        }
+                        Ok(errno)
+                    })
+                },
+            )
+            .map_err(Error::msg)?;
+        linker.allow_shadowing(false);
+        Ok(())
+    }
+}
+
+/// Calls the WASI functions replaced by `limit_writes`. They read their arguments in the
+/// memory exported by their caller, so they are called from `FORWARD_MODULE`, which exports
+/// the memory of the bot
+struct Forward {
+    module: Module,
+    /// Memory of the bot is imported first, before the WASI functions
+    imports: Vec<Extern>,
+    /// Instantiated at the first write, once the memory of the bot exists
+    instance: Mutex<Option<Instance>>,
+}
+
+impl Forward {
+    async fn call<T: Send, Params: WasmParams, Results: WasmResults>(
+        &self,
+        caller: &mut Caller<'_, T>,
+        name: &str,
+        params: Params,
+    ) -> wasmtime::Result<Results> {
+        let instance = *self.instance.lock().unwrap();
+        let instance = match instance {
+            Some(instance) => instance,
+            None => {
+                let memory = caller
+                    .get_export("memory")
+                    .ok_or(wasmtime::Error::msg("The bot exports no memory"))?;
+                let imports: Vec<Extern> = std::iter::once(memory)
+                    .chain(self.imports.iter().cloned())
+                    .collect();
+                let instance = Instance::new_async(&mut *caller, &self.module, &imports).await?;
+                *self.instance.lock().unwrap() = Some(instance);
+                instance
+            }
+        };
+        instance
+            .get_typed_func::<Params, Results>(&mut *caller, name)?
+            .call_async(&mut *caller, params)
+            .await
+    }
+}
+
+impl Drop for ScratchWatch {
+    fn drop(&mut self) {
+        self.state.done.store(true, Ordering::Release);
+    }
+}
+
+/// Total length of the `iovec`s of a write, read in the memory of the guest
+fn iovs_length<T>(caller: &mut Caller<'_, T>, iovs: i32, iovs_len: i32) -> u64 {
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return 0;
+    };
+    let data = memory.data(&*caller);
+    (0..iovs_len as u32 as usize)
+        .filter_map(|index| {
+            let offset = iovs as u32 as usize + index * 8 + 4;
+            read_u32(data, offset)
+        })
+        .map(u64::from)
+        .sum()
+}
+
+/// Count the bytes written by a successful write
+fn count_written<T>(caller: &mut Caller<'_, T>, state: &WatchState, errno: i32, written: i32) {
+    if errno != 0 {
+        return;
+    }
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return;
+    };
+    if let Some(written) = read_u32(memory.data(&*caller), written as u32 as usize) {
+        *state.used.lock().unwrap() += u64::from(written);
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl Runner {
+    pub fn scratch_quota(&self) -> u64 {
+        self.limits.scratch
+    }
+
+    /// Create the scratch of a run and watch its size until the returned watch is dropped
+    pub fn watch_scratch(
+        &self,
+        path: &Path,
+        cancel: Arc<AtomicBool>,
+    ) -> std::io::Result<ScratchWatch> {
+        std::fs::create_dir_all(path)?;
+        let state = Arc::new(WatchState {
+            path: path.to_path_buf(),
+            quota: self.limits.scratch,
+            used: Mutex::new(scratch_size(path)?),
+            done: AtomicBool::new(false),
+            exceeded: AtomicBool::new(false),
+            cancel,
+        });
+        let watched = state.clone();
+        std::thread::spawn(move || {
+            while !watched.done.load(Ordering::Acquire) {
+                if scratch_size(&watched.path).is_ok_and(|size| size > watched.quota) {
+                    debug!("Scratch {} above its quota", watched.path.display());
+                    watched.exceed();
+                    return;
+                }
+                std::thread::sleep(SCRATCH_CHECK_INTERVAL);
+            }
+        });
+        Ok(ScratchWatch { state })
+    }
+}
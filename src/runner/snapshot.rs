@@ -0,0 +1,528 @@
+use crate::runner::languages::Language;
+use crate::runner::{watchdog, Program, RunKind, Runner};
+use eyre::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::instrument;
+use tracing::log::{debug, warn};
+use wasm_encoder::{
+    ConstExpr, DataCountSection, DataSection, ExportKind, ExportSection, GlobalSection, GlobalType,
+    MemorySection, MemoryType, RawSection, ValType,
+};
+use wasmparser::{DataKind, ExternalKind, Parser, Payload, TypeRef};
+use wasmtime::{Instance, Module, Store, Val};
+use wasmtime_wasi::pipe::MemoryOutputPipe;
+
+/// Snapshot of the runtime of a bot, written in the folder of the bot
+pub const SNAPSHOT_FILE: &str = "snapshot.wasm";
+/// Snapshot being written, renamed to `SNAPSHOT_FILE` once complete
+pub const SNAPSHOT_TMP_FILE: &str = "snapshot.tmp";
+/// Prefix of the exports added to read the globals and the memories after the initialization
+const GLOBAL_EXPORT: &str = "__snapshot_global_";
+const MEMORY_EXPORT: &str = "__snapshot_memory_";
+/// Zero bytes kept inside a data segment of the snapshot rather than starting a new segment
+const MAX_DATA_GAP: usize = 4096;
+
+/// State of an instance after its initialization
+struct InstanceState {
+    globals: Vec<Val>,
+    /// Size in pages and content of each memory
+    memories: Vec<(u64, Vec<u8>)>,
+}
+
+impl Runner {
+    /// Snapshot the runtime of a bot file in the background when its language allows it, the
+    /// moves run the runtime from its start until the snapshot is ready. The initialization is
+    /// killed with the game, and its random bytes come from the seed of the game
+    #[instrument(skip(self, language))]
+    pub async fn start_snapshot(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        language: &Language,
+        seed: u64,
+    ) -> eyre::Result<()> {
+        let Some(snapshot) = &language.snapshot else {
+            return Ok(());
+        };
+        let mut init = language.clone();
+        init.args = snapshot.args.clone();
+        init.snapshot = None;
+        let mut program = self.program(file_path, &init).await?;
+        program.seed = seed;
+        let init_export = snapshot.init.clone();
+        let snapshot_path = file_path.with_file_name(SNAPSHOT_FILE);
+        let guard = self.register(game_id, RunKind::Snapshot)?;
+        let runner = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = runner
+                .snapshot(&program, &init_export, &snapshot_path, &guard.cancel)
+                .await
+            {
+                warn!(
+                    "No snapshot for {}, it runs from the start: {}",
+                    snapshot_path.display(),
+                    error
+                );
+            }
+            drop(guard);
+        });
+        Ok(())
+    }
+
+    /// Run the initialization of a runtime, then write its memories and globals in a new
+    /// module and compile it
+    async fn snapshot(
+        &self,
+        program: &Program,
+        init: &str,
+        snapshot_path: &Path,
+        cancel: &Arc<AtomicBool>,
+    ) -> eyre::Result<()> {
+        let runtime = self.module(&program.module_path).await?;
+        if runtime.get_export(init).is_none() {
+            return Err(Error::msg(format!(
+                "{} does not export {}",
+                program.module_path.display(),
+                init
+            )));
+        }
+        let bytes = tokio::fs::read(&program.module_path).await?;
+        let instrumented = self.instrumented(&program.module_path, &bytes).await?;
+
+        debug!("Initialize {}", program.module_path.display());
+        let stderr = MemoryOutputPipe::new(self.limits.stderr);
+        let init_stderr = stderr.clone();
+        let (mut store, linker) = self.wasi_store(
+            program,
+            |wasi| {
+                wasi.stderr(init_stderr);
+            },
+            cancel.clone(),
+            true,
+            Some(self.limits.snapshot),
+        )?;
+        let result: wasmtime::Result<InstanceState> = watchdog(
+            async {
+                let instance = linker.instantiate_async(&mut store, &instrumented).await?;
+                instance
+                    .get_typed_func::<(), ()>(&mut store, init)?
+                    .call_async(&mut store, ())
+                    .await?;
+                Ok(instance_state(&mut store, &instance))
+            },
+            cancel,
+            Some(self.limits.snapshot),
+        )
+        .await;
+        let state = result.map_err(|error| {
+            Error::msg(format!(
+                "The initialization failed with {}:\n{}",
+                error,
+                String::from_utf8_lossy(&stderr.contents())
+            ))
+        })?;
+
+        // The game may be over and its folder removed
+        if cancel.load(Ordering::Acquire) {
+            return Err(Error::msg("The game is over"));
+        }
+        // Written aside then renamed, so the moves never read a partial snapshot
+        let tmp_path = snapshot_path.with_file_name(SNAPSHOT_TMP_FILE);
+        tokio::fs::write(&tmp_path, snapshot_module(&bytes, &state)?).await?;
+        tokio::fs::rename(&tmp_path, snapshot_path).await?;
+        self.module(snapshot_path).await?;
+        debug!("Snapshot {} ready", snapshot_path.display());
+        Ok(())
+    }
+
+    /// Runtime exporting its globals and memories, compiled once per runtime
+    async fn instrumented(&self, path: &Path, bytes: &[u8]) -> eyre::Result<Module> {
+        if let Some(module) = self.cache.lock().unwrap().instrumented.get(path) {
+            return Ok(module.clone());
+        }
+        let instrumented = instrument(bytes)?;
+        let engine = self.engine.clone();
+        let module = tokio::task::spawn_blocking(move || Module::new(&engine, instrumented))
+            .await?
+            .map_err(Error::msg)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .instrumented
+            .insert(path.to_path_buf(), module.clone());
+        Ok(module)
+    }
+}
+
+/// Snapshot of a bot file if it is ready
+pub fn snapshot_path(file_path: &Path, language: &Language) -> Option<PathBuf> {
+    language.snapshot.as_ref()?;
+    let snapshot_path = file_path.with_file_name(SNAPSHOT_FILE);
+    snapshot_path.exists().then_some(snapshot_path)
+}
+
+/// Globals and memories of an instance of an instrumented module
+fn instance_state<T>(store: &mut Store<T>, instance: &Instance) -> InstanceState {
+    let globals = (0..)
+        .map_while(|index| instance.get_global(&mut *store, &format!("{}{}", GLOBAL_EXPORT, index)))
+        .collect::<Vec<_>>();
+    let memories = (0..)
+        .map_while(|index| instance.get_memory(&mut *store, &format!("{}{}", MEMORY_EXPORT, index)))
+        .collect::<Vec<_>>();
+    InstanceState {
+        globals: globals
+            .iter()
+            .map(|global| global.get(&mut *store))
+            .collect(),
+        memories: memories
+            .iter()
+            .map(|memory| (memory.size(&*store), memory.data(&*store).to_vec()))
+            .collect(),
+    }
+}
+
+/// Copy of a module exporting its defined globals and memories
+fn instrument(bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    let (mut imported_globals, mut imported_memories) = (0, 0);
+    let (mut globals, mut memories) = (0, 0);
+    let mut module = wasm_encoder::Module::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload?;
+        match &payload {
+            Payload::ImportSection(imports) => {
+                for import in imports.clone() {
+                    match import?.ty {
+                        TypeRef::Global(_) => imported_globals += 1,
+                        TypeRef::Memory(_) => imported_memories += 1,
+                        _ => (),
+                    }
+                }
+            }
+            Payload::GlobalSection(section) => globals = section.count(),
+            Payload::MemorySection(section) => memories = section.count(),
+            Payload::ExportSection(exports) => {
+                let mut section = ExportSection::new();
+                for export in exports.clone() {
+                    let export = export?;
+                    section.export(export.name, export_kind(export.kind), export.index);
+                }
+                for index in 0..globals {
+                    section.export(
+                        &format!("{}{}", GLOBAL_EXPORT, index),
+                        ExportKind::Global,
+                        imported_globals + index,
+                    );
+                }
+                for index in 0..memories {
+                    section.export(
+                        &format!("{}{}", MEMORY_EXPORT, index),
+                        ExportKind::Memory,
+                        imported_memories + index,
+                    );
+                }
+                module.section(&section);
+                continue;
+            }
+            _ => (),
+        }
+        if let Some((id, range)) = payload.as_section() {
+            module.section(&RawSection {
+                id,
+                data: &bytes[range],
+            });
+        }
+    }
+    Ok(module.finish())
+}
+
+/// Copy of a module starting with the globals and memories of an initialized instance, without
+/// its start function
+fn snapshot_module(bytes: &[u8], state: &InstanceState) -> eyre::Result<Vec<u8>> {
+    let has_data = Parser::new(0)
+        .parse_all(bytes)
+        .any(|payload| matches!(payload, Ok(Payload::DataSection(_))));
+    let mut imported_memories = 0;
+    let mut memory64 = Vec::new();
+    let mut module = wasm_encoder::Module::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload?;
+        match &payload {
+            Payload::ImportSection(imports) => {
+                for import in imports.clone() {
+                    if let TypeRef::Memory(_) = import?.ty {
+                        imported_memories += 1;
+                    }
+                }
+            }
+            Payload::GlobalSection(globals) => {
+                let mut section = GlobalSection::new();
+                for (global, value) in globals.clone().into_iter().zip(&state.globals) {
+                    let ty = global?.ty;
+                    section.global(
+                        GlobalType {
+                            val_type: val_type(ty.content_type)?,
+                            mutable: ty.mutable,
+                            shared: ty.shared,
+                        },
+                        &const_expr(value)?,
+                    );
+                }
+                module.section(&section);
+                continue;
+            }
+            Payload::MemorySection(memories) => {
+                let mut section = MemorySection::new();
+                for (memory, (pages, _)) in memories.clone().into_iter().zip(&state.memories) {
+                    let memory = memory?;
+                    memory64.push(memory.memory64);
+                    section.memory(MemoryType {
+                        minimum: *pages,
+                        maximum: memory.maximum,
+                        memory64: memory.memory64,
+                        shared: memory.shared,
+                        page_size_log2: memory.page_size_log2,
+                    });
+                }
+                module.section(&section);
+                continue;
+            }
+            // The start function already ran during the initialization
+            Payload::StartSection { .. } => continue,
+            Payload::DataCountSection { count, .. } => {
+                let segments = data_segments(state, &memory64, imported_memories);
+                module.section(&DataCountSection {
+                    count: count + segments.len() as u32,
+                });
+                continue;
+            }
+            Payload::DataSection(data) => {
+                let mut section = DataSection::new();
+                // The segments keep their index for `memory.init`, the active ones are emptied
+                // because the memories already hold their content
+                for segment in data.clone() {
+                    let segment = segment?;
+                    match segment.kind {
+                        DataKind::Passive => section.passive(segment.data.iter().copied()),
+                        DataKind::Active { .. } => section.passive([]),
+                    };
+                }
+                add_data_segments(&mut section, state, &memory64, imported_memories);
+                module.section(&section);
+                continue;
+            }
+            _ => (),
+        }
+        if let Some((id, range)) = payload.as_section() {
+            module.section(&RawSection {
+                id,
+                data: &bytes[range],
+            });
+        }
+        if matches!(payload, Payload::CodeSectionStart { .. }) && !has_data {
+            let mut section = DataSection::new();
+            add_data_segments(&mut section, state, &memory64, imported_memories);
+            module.section(&section);
+        }
+    }
+    Ok(module.finish())
+}
+
+/// Memory index, offset and bytes of the non-zero parts of the memories
+fn data_segments<'a>(
+    state: &'a InstanceState,
+    memory64: &[bool],
+    imported_memories: u32,
+) -> Vec<(u32, ConstExpr, &'a [u8])> {
+    let mut segments = Vec::new();
+    for (index, (_, data)) in state.memories.iter().enumerate() {
+        let memory64 = memory64.get(index).copied().unwrap_or_default();
+        let mut position = 0;
+        while let Some(start) = data[position..].iter().position(|&byte| byte != 0) {
+            let start = position + start;
+            let mut end = start;
+            let mut zeros = 0;
+            for (offset, &byte) in data[start..].iter().enumerate() {
+                if byte == 0 {
+                    zeros += 1;
+                    if zeros > MAX_DATA_GAP {
+                        break;
+                    }
+                } else {
+                    zeros = 0;
+                    end = start + offset + 1;
+                }
+            }
+            let offset = if memory64 {
+                ConstExpr::i64_const(start as i64)
+            } else {
+                ConstExpr::i32_const(start as i32)
+            };
+            segments.push((imported_memories + index as u32, offset, &data[start..end]));
+            position = end;
+        }
+    }
+    segments
+}
+
+fn add_data_segments(
+    section: &mut DataSection,
+    state: &InstanceState,
+    memory64: &[bool],
+    imported_memories: u32,
+) {
+    for (memory_index, offset, data) in data_segments(state, memory64, imported_memories) {
+        section.active(memory_index, &offset, data.iter().copied());
+    }
+}
+
+fn export_kind(kind: ExternalKind) -> ExportKind {
+    match kind {
+        ExternalKind::Func => ExportKind::Func,
+        ExternalKind::Table => ExportKind::Table,
+        ExternalKind::Memory => ExportKind::Memory,
+        ExternalKind::Global => ExportKind::Global,
+        ExternalKind::Tag => ExportKind::Tag,
+    }
+}
+
+fn val_type(ty: wasmparser::ValType) -> eyre::Result<ValType> {
+    match ty {
+        wasmparser::ValType::I32 => Ok(ValType::I32),
+        wasmparser::ValType::I64 => Ok(ValType::I64),
+        wasmparser::ValType::F32 => Ok(ValType::F32),
+        wasmparser::ValType::F64 => Ok(ValType::F64),
+        _ => Err(Error::msg(format!(
+            "Cannot snapshot a global of type {}",
+            ty
+        ))),
+    }
+}
+
+fn const_expr(value: &Val) -> eyre::Result<ConstExpr> {
+    match value {
+        Val::I32(value) => Ok(ConstExpr::i32_const(*value)),
+        Val::I64(value) => Ok(ConstExpr::i64_const(*value)),
+        Val::F32(bits) => Ok(ConstExpr::f32_const(f32::from_bits(*bits))),
+        Val::F64(bits) => Ok(ConstExpr::f64_const(f64::from_bits(*bits))),
+        _ => Err(Error::msg("Cannot snapshot a global holding a reference")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::Engine;
+
+    /// A start function and an `init` export changing globals and memory, and a passive data
+    /// segment whose `memory.init` needs the data count section
+    const RUNTIME: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $counter (mut i32) (i32.const 1))
+        (global $big (mut i64) (i64.const 0))
+        (global $ratio (mut f64) (f64.const 0))
+        (data $passive "passive")
+        (data (i32.const 16) "active")
+        (func $start
+            (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+        (start $start)
+        (func (export "init")
+            (global.set $counter (i32.mul (global.get $counter) (i32.const 10)))
+            (global.set $big (i64.const 0x1234567890))
+            (global.set $ratio (f64.const 0.5))
+            (i32.store (i32.const 20000) (i32.const 42)))
+        (func (export "counter") (result i32) (global.get $counter))
+        (func (export "big") (result i64) (global.get $big))
+        (func (export "ratio") (result f64) (global.get $ratio))
+        (func (export "copy_passive")
+            (memory.init $passive (i32.const 100) (i32.const 0) (i32.const 7))))"#;
+
+    fn initialized(engine: &Engine, bytes: &[u8]) -> InstanceState {
+        let module = Module::new(engine, instrument(bytes).unwrap()).unwrap();
+        let mut store = Store::new(engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        instance
+            .get_typed_func::<(), ()>(&mut store, "init")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+        instance_state(&mut store, &instance)
+    }
+
+    #[test]
+    fn snapshot_keeps_globals_memory_and_passive_segments() {
+        let bytes = wat::parse_str(RUNTIME).unwrap();
+        assert!(Parser::new(0)
+            .parse_all(&bytes)
+            .any(|payload| matches!(payload, Ok(Payload::DataCountSection { .. }))));
+        let engine = Engine::default();
+        let state = initialized(&engine, &bytes);
+        let snapshot = snapshot_module(&bytes, &state).unwrap();
+        wasmparser::Validator::new()
+            .validate_all(&snapshot)
+            .unwrap();
+
+        let module = Module::new(&engine, &snapshot).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let call = |store: &mut Store<()>, name: &str| {
+            instance
+                .get_typed_func::<(), ()>(&mut *store, name)
+                .unwrap()
+                .call(&mut *store, ())
+                .unwrap()
+        };
+        // The start function does not run again
+        let counter = instance.get_typed_func::<(), i32>(&mut store, "counter");
+        assert_eq!(counter.unwrap().call(&mut store, ()).unwrap(), 20);
+        let big = instance.get_typed_func::<(), i64>(&mut store, "big");
+        assert_eq!(big.unwrap().call(&mut store, ()).unwrap(), 0x1234567890);
+        let ratio = instance.get_typed_func::<(), f64>(&mut store, "ratio");
+        assert_eq!(ratio.unwrap().call(&mut store, ()).unwrap(), 0.5);
+
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(&memory.data(&store)[16..22], b"active");
+        assert_eq!(&memory.data(&store)[20000..20004], &42i32.to_le_bytes());
+        call(&mut store, "copy_passive");
+        assert_eq!(&memory.data(&store)[100..107], b"passive");
+    }
+
+    #[test]
+    fn snapshot_of_a_module_without_data_section() {
+        let bytes = wat::parse_str(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "init") (i32.store8 (i32.const 5) (i32.const 7))))"#,
+        )
+        .unwrap();
+        let engine = Engine::default();
+        let state = initialized(&engine, &bytes);
+        let snapshot = snapshot_module(&bytes, &state).unwrap();
+        wasmparser::Validator::new()
+            .validate_all(&snapshot)
+            .unwrap();
+
+        let module = Module::new(&engine, &snapshot).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(memory.data(&store)[5], 7);
+    }
+
+    #[test]
+    fn data_segments_skip_long_zero_runs() {
+        let mut memory = vec![0; 3 * MAX_DATA_GAP];
+        memory[0] = 1;
+        memory[10] = 2;
+        memory[2 * MAX_DATA_GAP] = 3;
+        let state = InstanceState {
+            globals: Vec::new(),
+            memories: vec![(1, memory)],
+        };
+        let segments = data_segments(&state, &[false], 0);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].2.len(), 11);
+        assert_eq!(segments[1].2, &[3]);
+    }
+}
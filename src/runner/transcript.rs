@@ -0,0 +1,162 @@
+use crate::protocol::{Action, BotOutput, Protocol};
+use crate::runner::BotRun;
+use eyre::Error;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::instrument;
+use tracing::log::{debug, warn};
+
+/// Folder of the transcripts of the games, named by a random key since game ids start again at
+/// 0 when the bot restarts
+pub const TRANSCRIPT_DIR: &str = "./transcripts";
+/// Time a transcript is kept after its last turn
+pub const TRANSCRIPT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Interval between two removals of the expired transcripts
+const TRANSCRIPT_EXPIRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Size above which the turns of a game are not recorded anymore, to stay under the Discord
+/// upload limit
+pub const MAX_TRANSCRIPT_SIZE: u64 = 8 * 1024 * 1024;
+
+/// First line of a transcript, only its owner can download it
+#[derive(Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    pub game: usize,
+    pub owner: u64,
+}
+
+/// What the bot received and printed for one turn, one JSON line of a transcript
+#[derive(Serialize)]
+pub struct TurnRecord {
+    /// Number of moves played before this turn
+    pub turn: usize,
+    pub protocol: Protocol,
+    pub seed: u64,
+    /// Input of the bot, the JSON state for components
+    pub input: String,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit: String,
+    /// Actions parsed from the output, empty when it could not be parsed
+    pub actions: Vec<Action>,
+    pub error: Option<String>,
+    pub wall_time_ms: u128,
+    pub fuel: Option<u64>,
+}
+
+impl TurnRecord {
+    pub fn new(
+        turn: usize,
+        protocol: Protocol,
+        seed: u64,
+        input: String,
+        bot_run: &BotRun,
+        output: &eyre::Result<BotOutput>,
+    ) -> Self {
+        Self {
+            turn,
+            protocol,
+            seed,
+            input,
+            stdout: bot_run.stdout.clone(),
+            stderr: bot_run.stderr.clone(),
+            exit: bot_run.exit.to_string(),
+            actions: output
+                .as_ref()
+                .map(|output| output.actions.clone())
+                .unwrap_or_default(),
+            error: output.as_ref().err().map(ToString::to_string),
+            wall_time_ms: bot_run.stats.wall_time.as_millis(),
+            fuel: bot_run.stats.fuel,
+        }
+    }
+}
+
+/// Random key of a new transcript
+pub fn new_transcript_key() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+pub fn transcript_path(key: &str) -> PathBuf {
+    Path::new(TRANSCRIPT_DIR).join(format!("{}.jsonl", key))
+}
+
+/// Append a turn to a transcript, starting it with its header
+#[instrument(skip(header, record))]
+pub async fn record_turn(
+    key: &str,
+    header: &TranscriptHeader,
+    record: &TurnRecord,
+) -> eyre::Result<()> {
+    let path = transcript_path(key);
+    let size = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    let mut lines = String::new();
+    if size == 0 {
+        lines.push_str(&serde_json::to_string(header)?);
+        lines.push('\n');
+    }
+    lines.push_str(&serde_json::to_string(record)?);
+    lines.push('\n');
+    if size + lines.len() as u64 > MAX_TRANSCRIPT_SIZE {
+        debug!("Transcript of game {} is full", header.game);
+        return Ok(());
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    Ok(())
+}
+
+/// Header and turns of a transcript, if `user` is its owner
+#[instrument]
+pub async fn read_transcript(key: &str, user: u64) -> eyre::Result<(TranscriptHeader, Vec<u8>)> {
+    let gone = || Error::msg("This transcript is gone");
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(gone());
+    }
+    let mut transcript = tokio::fs::read(transcript_path(key))
+        .await
+        .map_err(|_| gone())?;
+    let header_end = transcript
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .ok_or_else(gone)?;
+    let header: TranscriptHeader = serde_json::from_slice(&transcript[..header_end])?;
+    if header.owner != user {
+        return Err(Error::msg(
+            "Only the owner of the game can download its transcript",
+        ));
+    }
+    transcript.drain(..=header_end);
+    Ok((header, transcript))
+}
+
+/// Remove the transcripts older than `TRANSCRIPT_TTL`, forever
+pub async fn expire_transcripts() {
+    let mut interval = tokio::time::interval(TRANSCRIPT_EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(error) = remove_expired_transcripts().await {
+            warn!("Cannot remove the expired transcripts: {}", error);
+        }
+    }
+}
+
+async fn remove_expired_transcripts() -> eyre::Result<()> {
+    let mut entries = tokio::fs::read_dir(TRANSCRIPT_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = entry.metadata().await?.modified()?;
+        if modified.elapsed().is_ok_and(|age| age > TRANSCRIPT_TTL) {
+            debug!("Transcript {} expired", entry.path().display());
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
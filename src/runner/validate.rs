@@ -0,0 +1,153 @@
+use crate::runner::languages::Language;
+use crate::runner::Runner;
+use eyre::Error;
+use std::path::Path;
+use tracing::instrument;
+use wasmparser::{ExternalKind, Parser, Payload, TypeRef, Validator};
+
+/// First bytes of every wasm module and component
+pub const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Functions of `wasi_snapshot_preview1` a core module can import, everything but the sockets
+const ALLOWED_WASI_IMPORTS: &[&str] = &[
+    "args_get",
+    "args_sizes_get",
+    "environ_get",
+    "environ_sizes_get",
+    "clock_res_get",
+    "clock_time_get",
+    "fd_advise",
+    "fd_allocate",
+    "fd_close",
+    "fd_datasync",
+    "fd_fdstat_get",
+    "fd_fdstat_set_flags",
+    "fd_fdstat_set_rights",
+    "fd_filestat_get",
+    "fd_filestat_set_size",
+    "fd_filestat_set_times",
+    "fd_pread",
+    "fd_prestat_get",
+    "fd_prestat_dir_name",
+    "fd_pwrite",
+    "fd_read",
+    "fd_readdir",
+    "fd_renumber",
+    "fd_seek",
+    "fd_sync",
+    "fd_tell",
+    "fd_write",
+    "path_create_directory",
+    "path_filestat_get",
+    "path_filestat_set_times",
+    "path_link",
+    "path_open",
+    "path_readlink",
+    "path_remove_directory",
+    "path_rename",
+    "path_symlink",
+    "path_unlink_file",
+    "poll_oneoff",
+    "proc_exit",
+    "sched_yield",
+    "random_get",
+];
+
+/// Whether an uploaded file is the text format of a module or a component
+pub fn is_wat(bytes: &[u8], filename: &str) -> bool {
+    if Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("wat"))
+    {
+        return true;
+    }
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(64)]);
+    let text = text.trim_start();
+    text.starts_with("(module") || text.starts_with("(component")
+}
+
+/// Compile the text format of a module or a component to its binary format
+#[instrument(skip(bytes))]
+pub fn compile_wat(bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    wat::parse_bytes(bytes)
+        .map(|wasm| wasm.into_owned())
+        .map_err(|error| Error::msg(format!("Cannot compile the wat file: {}", error)))
+}
+
+/// Check that an uploaded wasm file can be run: a valid component, or a valid core module
+/// exporting `_start` and importing only allowed WASI functions
+#[instrument(skip(bytes))]
+pub fn check_wasm(bytes: &[u8]) -> eyre::Result<()> {
+    if !bytes.starts_with(WASM_MAGIC) {
+        return Err(Error::msg("Not a wasm file, the magic bytes are missing"));
+    }
+    Validator::new()
+        .validate_all(bytes)
+        .map_err(|error| Error::msg(format!("Invalid wasm file: {}", error)))?;
+    if Parser::is_component(bytes) {
+        return Ok(());
+    }
+
+    let mut start = false;
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload? {
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    let import = import?;
+                    if import.module != "wasi_snapshot_preview1"
+                        || !matches!(import.ty, TypeRef::Func(_))
+                        || !ALLOWED_WASI_IMPORTS.contains(&import.name)
+                    {
+                        return Err(Error::msg(format!(
+                            "Import {}::{} is not allowed, bots can only import WASI functions",
+                            import.module, import.name
+                        )));
+                    }
+                }
+            }
+            Payload::ExportSection(exports) => {
+                for export in exports {
+                    let export = export?;
+                    if export.name == "_start" && export.kind == ExternalKind::Func {
+                        start = true;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    if !start {
+        return Err(Error::msg("The module does not export a _start function"));
+    }
+    Ok(())
+}
+
+impl Runner {
+    /// Run the syntax check of the language of an uploaded script, with the script on stdin
+    #[instrument(skip(self, language))]
+    pub async fn check_source(
+        &self,
+        game_id: usize,
+        file_path: &Path,
+        language: &Language,
+    ) -> eyre::Result<()> {
+        if language.check.is_empty() {
+            return Ok(());
+        }
+        let source = tokio::fs::read_to_string(file_path)
+            .await
+            .map_err(|error| Error::msg(format!("The script is not valid UTF-8: {}", error)))?;
+        let mut checker = language.clone();
+        checker.args = language.check.clone();
+        let program = self.program(file_path, &checker).await?;
+        let bot_run = self.run_program(game_id, program, None, source).await?;
+        if !bot_run.exit.is_success() {
+            return Err(Error::msg(format!(
+                "The {} script is invalid:\n{}",
+                language.name,
+                bot_run.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+}